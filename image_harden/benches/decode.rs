@@ -0,0 +1,32 @@
+//! Raw decoder throughput without the CLI's fork/seccomp/landlock overhead.
+//!
+//! Run with `cargo bench --bench decode`. Track regressions against a saved
+//! baseline: `cargo bench --bench decode -- --save-baseline main`, then
+//! `cargo bench --bench decode -- --baseline main` on the branch under test.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use image_harden::api::{decode_unsandboxed, MediaFormat};
+
+// Repository sample images: 350x208 palette PNG and 172x178 progressive JPEG
+const PNG: &[u8] = include_bytes!("../../nurbcup2si.png");
+const JPEG: &[u8] = include_bytes!("../../Example.jpg");
+// 128x128 single-frame diagonal gradient over a 256-color palette
+const GIF: &[u8] = include_bytes!("data/gradient_128.gif");
+
+fn bench_decoders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, format, data) in [
+        ("png_350x208", MediaFormat::Png, PNG),
+        ("jpeg_172x178", MediaFormat::Jpeg, JPEG),
+        ("gif_128x128", MediaFormat::Gif, GIF),
+    ] {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| decode_unsandboxed(format, black_box(data)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decoders);
+criterion_main!(benches);
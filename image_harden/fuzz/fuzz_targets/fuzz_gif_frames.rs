@@ -0,0 +1,20 @@
+#![no_main]
+
+use image_harden::api::MediaFormat;
+use image_harden::{decode_animation_frames, MAX_ANIMATION_FRAMES};
+use libfuzzer_sys::fuzz_target;
+
+// Fuzz target for the multi-frame GIF path: GCE parsing, disposal,
+// per-frame placement and palettes, and the animation frame/pixel budgets
+// Seeds: cargo fuzz run fuzz_gif_frames corpus/fuzz_gif_frames seeds/fuzz_gif_frames
+fuzz_target!(|data: &[u8]| {
+    if let Ok(frames) = decode_animation_frames(MediaFormat::Gif, data) {
+        assert!(frames.len() <= MAX_ANIMATION_FRAMES);
+        for frame in &frames {
+            assert_eq!(
+                frame.data.len(),
+                frame.width as usize * frame.height as usize * 4
+            );
+        }
+    }
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use image_harden::{strip_video_metadata, validate_video_container};
+use libfuzzer_sys::fuzz_target;
+
+// Fuzz in-place MP4/MKV metadata blanking: box and EBML walks over
+// attacker-controlled sizes. Stripping never changes the file length, and
+// the output must re-validate.
+// Seeds: seeds/fuzz_strip_video (faststart MP4 with udta/meta, MKV with Tags)
+fuzz_target!(|data: &[u8]| {
+    if let Ok(stripped) = strip_video_metadata(data) {
+        assert_eq!(stripped.len(), data.len());
+        validate_video_container(&stripped).expect("stripped video must re-validate");
+    }
+});
@@ -0,0 +1,40 @@
+#![no_main]
+
+use image_harden::{
+    decode_gif_full, decode_jpeg_full, decode_png_full, decode_webp_full, encode_png, DecodedImage,
+    ImageHardenError,
+};
+use libfuzzer_sys::fuzz_target;
+
+type Decoder = fn(&[u8]) -> Result<DecodedImage, ImageHardenError>;
+
+// Fuzz target for the transcode (CDR) path: whatever decodes must re-encode
+// to a PNG that decodes back to the same geometry, and a second round trip
+// through our own output must be stable
+// Seeds: cargo fuzz run fuzz_transcode corpus/fuzz_transcode seeds/fuzz_transcode
+fuzz_target!(|data: &[u8]| {
+    let decoders: [Decoder; 4] = [
+        decode_png_full,
+        decode_jpeg_full,
+        decode_gif_full,
+        decode_webp_full,
+    ];
+    for decode in decoders {
+        let image = match decode(data) {
+            Ok(image) => image,
+            Err(_) => continue,
+        };
+
+        let png = encode_png(&image).expect("decoded image must re-encode");
+        let round_trip = decode_png_full(&png).expect("re-encoded PNG must decode");
+        assert_eq!(
+            (round_trip.width, round_trip.height),
+            (image.width, image.height)
+        );
+
+        let again = encode_png(&round_trip).expect("round trip must re-encode");
+        let stable = encode_png(&decode_png_full(&again).expect("second round trip must decode"))
+            .expect("second round trip must re-encode");
+        assert_eq!(again, stable);
+    }
+});
@@ -3,26 +3,40 @@
 //! that parent projects can depend on when the repository is consumed as a
 //! Git submodule.
 
+use crate::metrics;
+use crate::polyglot;
+use crate::structure::{walk_structure, StructureEvent};
 use crate::{
-    decode_flac, decode_gif, decode_heif, decode_jpeg, decode_mp3, decode_png, decode_svg,
-    decode_video, decode_vorbis, decode_webp, AudioData, ImageHardenError,
+    check_aspect_ratio, check_min_dimension, check_nonzero_dimensions, check_output_size,
+    check_strict_color, decode_flac, decode_gif_full, decode_heif_with_warnings, decode_jpeg_full,
+    decode_mp3, decode_png_with_warnings, decode_svg_rgba, decode_video, decode_vorbis,
+    decode_webp_full, encode_png, is_animated, probe_isobmff, read_image_dimensions, AudioData,
+    DecodedImage, ImageHardenError, IsoBmffKind, SvgDecoderConfig, DEFAULT_MAX_ASPECT_RATIO,
+    PNG_CHUNK_MALLOC_MAX,
 };
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "async")]
+use crate::for_each_animation_frame;
 #[cfg(feature = "avif")]
 use crate::formats::avif::decode_avif;
+use crate::formats::bmp::{decode_bmp, is_bmp};
 #[cfg(feature = "exif")]
 use crate::formats::exif::validate_exif;
 #[cfg(feature = "openexr")]
 use crate::formats::exr::decode_exr;
 #[cfg(feature = "icc")]
 use crate::formats::icc::validate_icc_profile;
+use crate::formats::ico::{decode_ico, is_ico};
 #[cfg(feature = "jxl")]
 use crate::formats::jxl::decode_jxl;
+use crate::formats::pnm::{decode_pnm, is_pnm};
 #[cfg(feature = "tiff")]
-use crate::formats::tiff::decode_tiff;
+use crate::formats::tiff::decode_tiff_full;
 
 /// Supported media types for the unified decoder entrypoint.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MediaFormat {
     Png,
     Jpeg,
@@ -38,12 +52,125 @@ pub enum MediaFormat {
     Tiff,
     #[cfg(feature = "openexr")]
     OpenExr,
+    Pnm,
+    Bmp,
+    Ico,
     AudioMp3,
     AudioVorbis,
     AudioFlac,
     VideoContainer,
 }
 
+impl MediaFormat {
+    /// Short lowercase name, matching `supported_formats` and metric labels.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaFormat::Png => "png",
+            MediaFormat::Jpeg => "jpeg",
+            MediaFormat::Gif => "gif",
+            MediaFormat::WebP => "webp",
+            MediaFormat::Heif => "heif",
+            MediaFormat::Svg => "svg",
+            #[cfg(feature = "avif")]
+            MediaFormat::Avif => "avif",
+            #[cfg(feature = "jxl")]
+            MediaFormat::JpegXl => "jpegxl",
+            #[cfg(feature = "tiff")]
+            MediaFormat::Tiff => "tiff",
+            #[cfg(feature = "openexr")]
+            MediaFormat::OpenExr => "openexr",
+            MediaFormat::Pnm => "pnm",
+            MediaFormat::Bmp => "bmp",
+            MediaFormat::Ico => "ico",
+            MediaFormat::AudioMp3 => "mp3",
+            MediaFormat::AudioVorbis => "vorbis",
+            MediaFormat::AudioFlac => "flac",
+            MediaFormat::VideoContainer => "video",
+        }
+    }
+}
+
+/// Identify the format from magic bytes alone, ignoring any file name.
+///
+/// ftyp files are routed with `probe_isobmff`; formats behind a disabled
+/// feature are reported as `None`, like unrecognized input.
+pub fn detect_format(data: &[u8]) -> Option<MediaFormat> {
+    let riff = |kind: &[u8]| data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == kind;
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(MediaFormat::Png);
+    }
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(MediaFormat::Jpeg);
+    }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some(MediaFormat::Gif);
+    }
+    if riff(b"WEBP") {
+        return Some(MediaFormat::WebP);
+    }
+    if data.len() >= 8 && &data[4..8] == b"ftyp" {
+        return match probe_isobmff(data) {
+            #[cfg(feature = "avif")]
+            IsoBmffKind::AvifStill | IsoBmffKind::AvifSequence => Some(MediaFormat::Avif),
+            IsoBmffKind::HeifStill | IsoBmffKind::HeifSequence => Some(MediaFormat::Heif),
+            IsoBmffKind::Mp4Video => Some(MediaFormat::VideoContainer),
+            _ => None,
+        };
+    }
+    if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) || riff(b"AVI ") {
+        return Some(MediaFormat::VideoContainer);
+    }
+    if data.starts_with(b"fLaC") {
+        return Some(MediaFormat::AudioFlac);
+    }
+    if data.starts_with(b"OggS") {
+        return Some(MediaFormat::AudioVorbis);
+    }
+    // ID3 tag, or a bare MPEG audio frame sync
+    if data.starts_with(b"ID3") || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0) {
+        return Some(MediaFormat::AudioMp3);
+    }
+    if is_pnm(data) {
+        return Some(MediaFormat::Pnm);
+    }
+    if is_bmp(data) {
+        return Some(MediaFormat::Bmp);
+    }
+    if is_ico(data) {
+        return Some(MediaFormat::Ico);
+    }
+    #[cfg(feature = "tiff")]
+    if data.starts_with(b"II\x2A\x00") || data.starts_with(b"MM\x00\x2A") {
+        return Some(MediaFormat::Tiff);
+    }
+    // Bare codestream or ISOBMFF-style container signature
+    #[cfg(feature = "jxl")]
+    if data.starts_with(&[0xFF, 0x0A]) || data.starts_with(b"\0\0\0\x0cJXL \r\n\x87\n") {
+        return Some(MediaFormat::JpegXl);
+    }
+    #[cfg(feature = "openexr")]
+    if data.starts_with(&[0x76, 0x2F, 0x31, 0x01]) {
+        return Some(MediaFormat::OpenExr);
+    }
+    if looks_like_svg(data) {
+        return Some(MediaFormat::Svg);
+    }
+    None
+}
+
+/// An XML declaration or `<svg` root, after an optional BOM and whitespace
+fn looks_like_svg(data: &[u8]) -> bool {
+    let text = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let start = text
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(text.len());
+    let text = &text[start..];
+    text.starts_with(b"<svg")
+        || (text.starts_with(b"<?xml") && text.windows(4).take(1024).any(|w| w == b"<svg"))
+}
+
 /// Decoder output variants.
 #[derive(Debug, Clone)]
 pub enum DecodedMedia {
@@ -52,11 +179,292 @@ pub enum DecodedMedia {
     Video(Vec<u8>),
 }
 
-/// Optional knobs for decoding. Currently only video uses an option
-/// to specify the sandboxed WASM path.
-#[derive(Debug, Default, Clone)]
+impl DecodedMedia {
+    /// Size of the decoded output in bytes.
+    pub fn byte_len(&self) -> usize {
+        match self {
+            DecodedMedia::Image(data) | DecodedMedia::Video(data) => data.len(),
+            DecodedMedia::Audio(audio) => audio.samples.len() * std::mem::size_of::<i16>(),
+        }
+    }
+}
+
+/// Per-decode measurements returned by `HardenedDecoder::decode_with_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Time spent in `decode_with_options`, validation included.
+    pub wall_time: Duration,
+    /// Output allocation plus the decoder's working set where it is known
+    /// (PNG, JPEG, GIF, WebP); other formats count the output only.
+    pub peak_bytes_estimate: usize,
+    pub input_bytes: usize,
+    pub output_bytes: usize,
+}
+
+/// Bytes a decoder holds beyond its output buffer, estimated from the
+/// header geometry.
+fn working_set_estimate(format: MediaFormat, data: &[u8], output_bytes: usize) -> usize {
+    let (width, height) = read_image_dimensions(data).unwrap_or((0, 0));
+    let (width, height) = (width as usize, height as usize);
+    let row_pointers = height.saturating_mul(std::mem::size_of::<usize>());
+    match format {
+        // Row pointers plus libpng's largest chunk allocation
+        MediaFormat::Png => row_pointers.saturating_add(PNG_CHUNK_MALLOC_MAX),
+        // One 16-line MCU row of RGB samples
+        MediaFormat::Jpeg => width.saturating_mul(3 * 16),
+        // GIFLIB's index raster for the frame, plus row pointers
+        MediaFormat::Gif => width.saturating_mul(height).saturating_add(row_pointers),
+        // libwebp's own buffer, copied into the output
+        MediaFormat::WebP => output_bytes,
+        _ => 0,
+    }
+}
+
+/// Unified-API limits, applied before and after the per-format decoder
+/// and on top of each decoder's own built-in caps. Start from a preset
+/// (`paranoid`, `balanced`, `permissive`) rather than setting every field.
+///
+/// Decoded pixels never carry the source's metadata, so there is no
+/// separate stripping knob here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest header width/height accepted for PNG/GIF/JPEG/WebP.
+    /// 0 leaves only the decoder's own limit.
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Largest input accepted, in bytes, for any format. 0 disables.
+    pub max_input_bytes: usize,
+    /// Reject PNG/GIF/JPEG/WebP images whose header declares a width or
+    /// height below this value (e.g. 2 to strip 1x1 tracking pixels).
+    /// 0 disables the check. Only `HardenedDecoder` applies it; the bare
+    /// `decode_*` functions do not.
+    pub min_dimension: u32,
+    /// Maximum longer:shorter side ratio for PNG/GIF/JPEG/WebP headers
+    /// (1000:1 when balanced). 0 disables the check. Like `min_dimension`,
+    /// it is not applied by the bare `decode_*` functions.
+    pub max_aspect_ratio: u32,
+    /// Accept animated GIF, APNG, and WebP input. Only the first frame is
+    /// decoded either way; rejecting keeps the frame parsers out of reach.
+    pub allow_animation: bool,
+    /// Reject PNG/JPEG/GIF files carrying a second image after the first
+    /// one ends, which other parsers may display instead.
+    pub reject_concatenated_images: bool,
+    /// Reject PNG/JPEG/GIF files with any bytes after the primary image.
+    pub reject_trailing_data: bool,
+    /// Reject PNG/JPEG color layouts that need the native libraries' rarer
+    /// transforms (sub-byte or out-of-spec PNG depths, 12-bit, CMYK, and
+    /// unusually subsampled JPEG); see `check_strict_color`.
+    pub strict_color: bool,
+    /// Reject PNGs on which libpng raises any warning (bad ancillary CRC,
+    /// known-incorrect sRGB profile, ...), and HEIF images whose colour
+    /// profile is missing or cannot be applied, instead of decoding them.
+    pub fail_on_warning: bool,
+    /// Check that each decoded image buffer is exactly width * height *
+    /// channels bytes before returning it (on in every preset). AVIF, JPEG
+    /// XL, and OpenEXR decoding is not implemented yet and never returns a
+    /// buffer to check.
+    pub verify_output_size: bool,
+}
+
+impl Limits {
+    /// Small still images only: 2048x2048 and 16 MiB at most, no 1x1
+    /// pixels, no animation, nothing after the image, strict color, and
+    /// no libpng warnings.
+    pub fn paranoid() -> Self {
+        Self {
+            max_width: 2048,
+            max_height: 2048,
+            max_input_bytes: 16 * 1024 * 1024,
+            min_dimension: 2,
+            max_aspect_ratio: 100,
+            allow_animation: false,
+            reject_concatenated_images: true,
+            reject_trailing_data: true,
+            strict_color: true,
+            fail_on_warning: true,
+            verify_output_size: true,
+        }
+    }
+
+    /// The default: built-in decoder caps, 1000:1 aspect ratio, and no
+    /// concatenated images.
+    pub fn balanced() -> Self {
+        Self {
+            max_width: 0,
+            max_height: 0,
+            max_input_bytes: 0,
+            min_dimension: 0,
+            max_aspect_ratio: DEFAULT_MAX_ASPECT_RATIO,
+            allow_animation: true,
+            reject_concatenated_images: true,
+            reject_trailing_data: false,
+            strict_color: false,
+            fail_on_warning: false,
+            verify_output_size: true,
+        }
+    }
+
+    /// Only the built-in decoder caps; for trusted or internal sources.
+    pub fn permissive() -> Self {
+        Self {
+            max_aspect_ratio: 0,
+            reject_concatenated_images: false,
+            ..Self::balanced()
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::balanced()
+    }
+}
+
+/// What `decode_checked` does when the sniffed format differs from the
+/// claimed one (e.g. a JPEG uploaded as `.png`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FormatTrust {
+    /// Decode as the detected format; the bytes, not the name, decide.
+    #[default]
+    Detected,
+    /// Refuse the input with `ValidationFailure`.
+    Reject,
+}
+
+/// Channel order of decoded image output. Honored by the PNG, JPEG, GIF,
+/// WebP, HEIF, TIFF, PNM, BMP, and ICO decoders, per sample at any bit
+/// depth; gray output is left as is. SVG, AVIF, JPEG XL, and OpenEXR only
+/// accept `Rgba`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelOrder {
+    /// R, G, B, A (RGB without alpha), as the decoders produce it.
+    #[default]
+    Rgba,
+    /// B, G, R, A for GDI and BGRA texture uploads; RGB output becomes BGR.
+    Bgra,
+    /// A, R, G, B; RGB output has no alpha to move and stays RGB.
+    Argb,
+}
+
+/// Reorder interleaved RGB/RGBA samples of `sample_bytes` each in place.
+fn reorder_channels(data: &mut [u8], channels: usize, sample_bytes: usize, order: ChannelOrder) {
+    let pixel_bytes = channels * sample_bytes;
+    match (order, channels) {
+        (ChannelOrder::Bgra, 3 | 4) => {
+            for pixel in data.chunks_exact_mut(pixel_bytes) {
+                let (red, rest) = pixel.split_at_mut(sample_bytes);
+                red.swap_with_slice(&mut rest[sample_bytes..2 * sample_bytes]);
+            }
+        }
+        (ChannelOrder::Argb, 4) => {
+            for pixel in data.chunks_exact_mut(pixel_bytes) {
+                pixel.rotate_right(sample_bytes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// False for decoders whose output has no channel layout to swizzle: SVG
+/// hands out PNG bytes, and AVIF, JPEG XL, and OpenEXR are not implemented.
+fn swizzle_supported(format: MediaFormat) -> bool {
+    match format {
+        MediaFormat::Svg => false,
+        #[cfg(feature = "avif")]
+        MediaFormat::Avif => false,
+        #[cfg(feature = "jxl")]
+        MediaFormat::JpegXl => false,
+        #[cfg(feature = "openexr")]
+        MediaFormat::OpenExr => false,
+        _ => true,
+    }
+}
+
+/// `decode_checked` output: the media plus the claimed and sniffed formats.
+#[derive(Debug, Clone)]
+pub struct CheckedDecode {
+    pub media: DecodedMedia,
+    pub claimed: MediaFormat,
+    /// `None` when no signature matched; the claimed format was used.
+    pub detected: Option<MediaFormat>,
+}
+
+impl CheckedDecode {
+    /// True when the input's magic bytes contradict the claimed format.
+    pub fn mismatched(&self) -> bool {
+        self.detected
+            .is_some_and(|detected| detected != self.claimed)
+    }
+}
+
+/// Optional knobs for decoding. Video uses an option to specify the
+/// sandboxed WASM path; `trace_id` correlates metered decodes in logs.
+#[derive(Debug, Clone, Default)]
 pub struct DecoderOptions {
     pub video_wasm_path: Option<String>,
+    /// Caller-supplied correlation id (e.g. an HTTP request id). When unset,
+    /// `decode_with_metrics` generates a UUID (otel feature only).
+    pub trace_id: Option<String>,
+    /// Size, geometry, and structure limits (balanced by default).
+    pub limits: Limits,
+    /// Claimed-vs-detected format policy for `decode_checked`.
+    pub trust: FormatTrust,
+    /// Channel order of decoded images (RGBA by default).
+    pub channel_order: ChannelOrder,
+    /// Formats this session may decode; anything else, including what
+    /// `decode_checked` detects, is refused before any decoder runs. `None`
+    /// allows every format the build supports.
+    pub allowed_formats: Option<HashSet<MediaFormat>>,
+}
+
+impl DecoderOptions {
+    /// Use a caller-supplied id instead of a generated UUID in decode logs.
+    pub fn with_trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Replace the limits, e.g. `DecoderOptions::default().with_limits(Limits::paranoid())`.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the policy for inputs whose magic bytes contradict their claimed format.
+    pub fn with_trust(mut self, trust: FormatTrust) -> Self {
+        self.trust = trust;
+        self
+    }
+
+    /// Emit decoded images in `order`, e.g. `ChannelOrder::Bgra` for GDI.
+    pub fn with_channel_order(mut self, order: ChannelOrder) -> Self {
+        self.channel_order = order;
+        self
+    }
+
+    /// Only decode `formats`, e.g. `[MediaFormat::Png, MediaFormat::Jpeg]`.
+    pub fn with_allowed_formats(mut self, formats: impl IntoIterator<Item = MediaFormat>) -> Self {
+        self.allowed_formats = Some(formats.into_iter().collect());
+        self
+    }
+}
+
+/// Maximum length of a caller-supplied trace id kept in logs.
+#[cfg(feature = "otel")]
+const MAX_TRACE_ID_LEN: usize = 128;
+
+/// Restrict caller-supplied ids to printable ASCII so they cannot forge
+/// log lines, falling back to a fresh UUID.
+#[cfg(feature = "otel")]
+fn decode_id(options: &DecoderOptions) -> String {
+    match &options.trace_id {
+        Some(id) if !id.is_empty() => id
+            .chars()
+            .filter(|c| c.is_ascii_graphic())
+            .take(MAX_TRACE_ID_LEN)
+            .collect(),
+        _ => uuid::Uuid::new_v4().to_string(),
+    }
 }
 
 /// Convenience wrapper that exposes a minimal surface area for downstream
@@ -81,21 +489,106 @@ impl HardenedDecoder {
         data: &[u8],
         options: &DecoderOptions,
     ) -> Result<DecodedMedia, ImageHardenError> {
-        match format {
-            MediaFormat::Png => decode_png(data).map(DecodedMedia::Image),
-            MediaFormat::Jpeg => decode_jpeg(data).map(DecodedMedia::Image),
-            MediaFormat::Gif => decode_gif(data).map(DecodedMedia::Image),
-            MediaFormat::WebP => decode_webp(data).map(DecodedMedia::Image),
-            MediaFormat::Heif => decode_heif(data).map(DecodedMedia::Image),
-            MediaFormat::Svg => decode_svg(data).map(DecodedMedia::Image),
+        if let Some(allowed) = &options.allowed_formats {
+            if !allowed.contains(&format) {
+                return Err(ImageHardenError::ValidationFailure(format!(
+                    "{} input is not allowed in this session",
+                    format.name()
+                )));
+            }
+        }
+
+        let limits = &options.limits;
+        if limits.max_input_bytes > 0 && data.len() > limits.max_input_bytes {
+            return Err(ImageHardenError::ResourceExhausted(format!(
+                "Input of {} bytes exceeds limit of {} bytes",
+                data.len(),
+                limits.max_input_bytes
+            )));
+        }
+
+        // Cheap geometry checks on the header before any decoder runs
+        if let Some((width, height)) = read_image_dimensions(data) {
+            check_nonzero_dimensions(width, height)?;
+            if (limits.max_width > 0 && width > limits.max_width)
+                || (limits.max_height > 0 && height > limits.max_height)
+            {
+                return Err(ImageHardenError::ResourceExhausted(format!(
+                    "{}x{} image exceeds limit of {}x{}",
+                    width, height, limits.max_width, limits.max_height
+                )));
+            }
+            if limits.min_dimension > 0 {
+                check_min_dimension(width, height, limits.min_dimension)?;
+            }
+            if limits.max_aspect_ratio > 0 {
+                check_aspect_ratio(width, height, limits.max_aspect_ratio)?;
+            }
+        }
+
+        if limits.strict_color {
+            check_strict_color(format, data)?;
+        }
+
+        if !limits.allow_animation && is_animated(format, data) {
+            return Err(ImageHardenError::ValidationFailure(format!(
+                "Animated {} input is not allowed",
+                format.name()
+            )));
+        }
+
+        let order = options.channel_order;
+        if order != ChannelOrder::Rgba && !swizzle_supported(format) {
+            return Err(ImageHardenError::ValidationFailure(format!(
+                "{:?} channel order is not supported for {}",
+                order,
+                format.name()
+            )));
+        }
+
+        // Swizzle in the decoder's output buffer rather than a second copy
+        let image = |mut image: DecodedImage| -> Result<DecodedMedia, ImageHardenError> {
+            if limits.verify_output_size {
+                check_output_size(&image)?;
+            }
+            let sample_bytes = image.bit_depth.div_ceil(8) as usize;
+            reorder_channels(
+                &mut image.data,
+                image.channels as usize,
+                sample_bytes,
+                order,
+            );
+            Ok(DecodedMedia::Image(image.data))
+        };
+
+        let decoded = match format {
+            MediaFormat::Png => decode_png_with_warnings(data, limits.fail_on_warning)
+                .and_then(|(decoded, _)| image(decoded)),
+            MediaFormat::Jpeg => decode_jpeg_full(data).and_then(image),
+            MediaFormat::Gif => decode_gif_full(data).and_then(image),
+            MediaFormat::WebP => decode_webp_full(data).and_then(image),
+            MediaFormat::Heif => decode_heif_with_warnings(data, limits.fail_on_warning)
+                .and_then(|(decoded, _)| image(decoded)),
+            // Checked as RGBA, then handed out as PNG like decode_svg
+            MediaFormat::Svg => {
+                decode_svg_rgba(data, &SvgDecoderConfig::default()).and_then(|decoded| {
+                    if limits.verify_output_size {
+                        check_output_size(&decoded)?;
+                    }
+                    encode_png(&decoded).map(DecodedMedia::Image)
+                })
+            }
             #[cfg(feature = "avif")]
             MediaFormat::Avif => decode_avif(data).map(DecodedMedia::Image),
             #[cfg(feature = "jxl")]
             MediaFormat::JpegXl => decode_jxl(data).map(DecodedMedia::Image),
             #[cfg(feature = "tiff")]
-            MediaFormat::Tiff => decode_tiff(data).map(DecodedMedia::Image),
+            MediaFormat::Tiff => decode_tiff_full(data).and_then(image),
             #[cfg(feature = "openexr")]
             MediaFormat::OpenExr => decode_exr(data).map(DecodedMedia::Image),
+            MediaFormat::Pnm => decode_pnm(data).and_then(image),
+            MediaFormat::Bmp => decode_bmp(data).and_then(image),
+            MediaFormat::Ico => decode_ico(data).and_then(image),
             MediaFormat::AudioMp3 => decode_mp3(data).map(DecodedMedia::Audio),
             MediaFormat::AudioVorbis => decode_vorbis(data).map(DecodedMedia::Audio),
             MediaFormat::AudioFlac => decode_flac(data).map(DecodedMedia::Audio),
@@ -103,15 +596,328 @@ impl HardenedDecoder {
                 decode_video(data, options.video_wasm_path.as_deref().unwrap_or(""))
                     .map(DecodedMedia::Video)
             }
+        }?;
+
+        // Decoders stop at the first image's end; a smuggled second image
+        // would be shown by viewers that pick the last one
+        if matches!(
+            format,
+            MediaFormat::Png | MediaFormat::Jpeg | MediaFormat::Gif
+        ) {
+            if limits.reject_concatenated_images {
+                if let Some((offset, kind)) = polyglot::find_concatenated_image(data) {
+                    return Err(ImageHardenError::ValidationFailure(format!(
+                        "Concatenated {} image at offset {} after the primary image",
+                        kind, offset
+                    )));
+                }
+            }
+            if limits.reject_trailing_data {
+                if let Some(end) = polyglot::primary_image_end(data) {
+                    if end < data.len() {
+                        return Err(ImageHardenError::ValidationFailure(format!(
+                            "{} bytes of trailing data after the primary image",
+                            data.len() - end
+                        )));
+                    }
+                }
+            }
+        }
+
+        Ok(decoded)
+    }
+
+    /// Decode input claimed to be `claimed` after sniffing its real format
+    /// with `detect_format`. A contradiction is counted as
+    /// `SUSPICIOUS_PATTERNS_TOTAL{pattern="extension_mismatch"}` and then
+    /// handled per `options.trust`. Input with no recognizable signature is
+    /// decoded as claimed.
+    pub fn decode_checked(
+        claimed: MediaFormat,
+        data: &[u8],
+        options: &DecoderOptions,
+    ) -> Result<CheckedDecode, ImageHardenError> {
+        let detected = detect_format(data);
+        let format = match detected {
+            Some(detected) if detected != claimed => {
+                metrics::SUSPICIOUS_PATTERNS_TOTAL
+                    .with_label_values(&["extension_mismatch", claimed.name()])
+                    .inc();
+                if options.trust == FormatTrust::Reject {
+                    return Err(ImageHardenError::ValidationFailure(format!(
+                        "Input claimed as {} is {}",
+                        claimed.name(),
+                        detected.name()
+                    )));
+                }
+                detected
+            }
+            _ => claimed,
+        };
+        let media = Self::decode_with_options(format, data, options)?;
+        Ok(CheckedDecode {
+            media,
+            claimed,
+            detected,
+        })
+    }
+
+    /// `decode_with_options`, first reporting each PNG chunk, JPEG segment,
+    /// or ISOBMFF box to `on_structure` in file order. Events are delivered
+    /// even when the decode then fails; `decode_with_options` itself never
+    /// walks the structure.
+    pub fn decode_with_structure<'a>(
+        format: MediaFormat,
+        data: &'a [u8],
+        options: &DecoderOptions,
+        on_structure: impl FnMut(StructureEvent<'a>),
+    ) -> Result<DecodedMedia, ImageHardenError> {
+        walk_structure(format, data, on_structure);
+        Self::decode_with_options(format, data, options)
+    }
+
+    /// Decode with default options and report wall time, input/output size,
+    /// and an estimate of peak memory for the attempt.
+    pub fn decode_with_stats(
+        format: MediaFormat,
+        data: &[u8],
+    ) -> Result<(DecodedMedia, DecodeStats), ImageHardenError> {
+        let start = Instant::now();
+        let decoded = Self::decode_with_options(format, data, &DecoderOptions::default())?;
+        let wall_time = start.elapsed();
+
+        let output_bytes = decoded.byte_len();
+        let stats = DecodeStats {
+            wall_time,
+            peak_bytes_estimate: output_bytes.saturating_add(working_set_estimate(
+                format,
+                data,
+                output_bytes,
+            )),
+            input_bytes: data.len(),
+            output_bytes,
+        };
+        Ok((decoded, stats))
+    }
+
+    /// Decode and record Prometheus metrics for the attempt (in-flight count,
+    /// duration, input size, output volume, amplification ratio, failures by
+    /// error kind).
+    pub fn decode_with_metrics(
+        format: MediaFormat,
+        data: &[u8],
+        options: &DecoderOptions,
+    ) -> Result<DecodedMedia, ImageHardenError> {
+        let label = format.name();
+        let _in_flight = metrics::InFlightGuard::new(label);
+
+        #[cfg(feature = "otel")]
+        let decode_id = decode_id(options);
+        #[cfg(feature = "otel")]
+        let span = tracing::info_span!(
+            "media.decode",
+            decode.id = decode_id.as_str(),
+            media.format = label,
+            media.input_bytes = data.len(),
+            media.output_bytes = tracing::field::Empty,
+            media.error_kind = tracing::field::Empty,
+        );
+        #[cfg(feature = "otel")]
+        let _entered = span.enter();
+
+        let start = Instant::now();
+        let result = Self::decode_with_options(format, data, options);
+
+        match &result {
+            Ok(decoded) => {
+                metrics::record_file_processed(label, data.len(), start.elapsed().as_secs_f64());
+                metrics::record_decoded_bytes(label, decoded.byte_len());
+                metrics::record_decode_amplification(label, data.len(), decoded.byte_len());
+                #[cfg(feature = "otel")]
+                span.record("media.output_bytes", decoded.byte_len());
+            }
+            Err(e) => {
+                metrics::record_file_failed(label, e.kind());
+                #[cfg(feature = "otel")]
+                span.record("media.error_kind", e.kind());
+            }
+        }
+
+        // One structured line per decode for post-incident correlation
+        #[cfg(feature = "otel")]
+        {
+            let (outcome, output_bytes, error_kind) = match &result {
+                Ok(decoded) => ("success", decoded.byte_len(), ""),
+                Err(e) => ("failure", 0, e.kind()),
+            };
+            tracing::info!(
+                target: "image_harden::decode",
+                decode.id = decode_id.as_str(),
+                media.format = label,
+                outcome,
+                media.input_bytes = data.len(),
+                media.output_bytes = output_bytes,
+                error_kind,
+                "decode finished"
+            );
+        }
+
+        result
+    }
+}
+
+/// Decode in the calling process, bypassing OS-level isolation.
+///
+/// The CLI runs decoders in a child with PID/network/mount namespaces,
+/// seccomp, and Landlock; this entry point does none of that. Input
+/// validation and resource limits still apply, but a memory-safety bug in a C
+/// codec runs with the caller's privileges. Use it for benchmarks, or when
+/// the embedding process provides its own sandbox.
+pub fn decode_unsandboxed(
+    format: MediaFormat,
+    data: &[u8],
+) -> Result<DecodedMedia, ImageHardenError> {
+    HardenedDecoder::decode(format, data)
+}
+
+/// Decodes running inside `decode_async_limited`'s blocking tasks, and the
+/// most seen at once, so tests can observe real concurrency.
+#[cfg(all(test, feature = "async"))]
+static LIMITED_DECODES_RUNNING: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+#[cfg(all(test, feature = "async"))]
+static LIMITED_DECODES_PEAK: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+#[cfg(feature = "async")]
+impl HardenedDecoder {
+    /// Decode on Tokio's blocking pool so the C decoders do not stall the
+    /// async runtime.
+    ///
+    /// None of the decoders can be interrupted mid-image: dropping the
+    /// returned future stops waiting for the result, but the blocking decode
+    /// still runs to completion within its usual limits.
+    pub async fn decode_async(
+        format: MediaFormat,
+        data: Vec<u8>,
+    ) -> Result<DecodedMedia, ImageHardenError> {
+        Self::decode_async_with_options(format, data, DecoderOptions::default()).await
+    }
+
+    /// `decode_async` with explicit options.
+    pub async fn decode_async_with_options(
+        format: MediaFormat,
+        data: Vec<u8>,
+        options: DecoderOptions,
+    ) -> Result<DecodedMedia, ImageHardenError> {
+        let task =
+            tokio::task::spawn_blocking(move || Self::decode_with_options(format, &data, &options));
+        match task.await {
+            Ok(result) => result,
+            // Surface decoder panics exactly as the blocking call would
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(ImageHardenError::IoError(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                format!("decode task cancelled: {}", e),
+            ))),
+        }
+    }
+
+    /// `decode_async_with_options` bounded by a semaphore shared across the
+    /// service: the decode waits for a permit before it is handed to
+    /// `spawn_blocking`, so queued work stays off the blocking pool.
+    ///
+    /// The permit moves into the blocking task and is released when the
+    /// decode actually finishes. Dropping the returned future after the
+    /// decode started therefore does not free the slot early. A closed
+    /// semaphore fails the decode with `ResourceExhausted`.
+    pub async fn decode_async_limited(
+        format: MediaFormat,
+        data: Vec<u8>,
+        options: DecoderOptions,
+        limiter: std::sync::Arc<tokio::sync::Semaphore>,
+    ) -> Result<DecodedMedia, ImageHardenError> {
+        let permit = limiter.acquire_owned().await.map_err(|_| {
+            ImageHardenError::ResourceExhausted("Decode semaphore is closed".to_string())
+        })?;
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            #[cfg(test)]
+            {
+                use std::sync::atomic::Ordering;
+                let running = LIMITED_DECODES_RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+                LIMITED_DECODES_PEAK.fetch_max(running, Ordering::SeqCst);
+            }
+            let result = Self::decode_with_options(format, &data, &options);
+            #[cfg(test)]
+            LIMITED_DECODES_RUNNING.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            result
+        });
+        match task.await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(ImageHardenError::IoError(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                format!("decode task cancelled: {}", e),
+            ))),
         }
     }
 }
 
+/// Stream of composited animation frames produced on the blocking pool.
+///
+/// At most one finished frame waits in the channel; dropping the stream
+/// stops decoding after the frame in progress.
+#[cfg(feature = "async")]
+pub struct FrameStream {
+    frames: tokio::sync::mpsc::Receiver<Result<DecodedImage, ImageHardenError>>,
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for FrameStream {
+    type Item = Result<DecodedImage, ImageHardenError>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.frames.poll_recv(cx)
+    }
+}
+
+/// Decode GIF, APNG, and animated WebP frames as a `Stream` of full RGBA
+/// canvases; still PNG, JPEG, and WebP yield their single image.
+///
+/// Frames are decoded as the consumer polls: each one is expanded and
+/// composited only after the previous one was taken, so at most one
+/// finished frame is buffered. (giflib still holds the whole GIF's color
+/// indices, one byte per pixel.) The animation frame count is checked
+/// before the first frame and the pixel budget as frames are produced; a
+/// decode error ends the stream. Must be called within a Tokio runtime.
+#[cfg(feature = "async")]
+pub fn decode_frames_stream(format: MediaFormat, data: Vec<u8>) -> FrameStream {
+    // Capacity 1 keeps at most one composited canvas queued
+    let (sender, frames) = tokio::sync::mpsc::channel(1);
+    tokio::task::spawn_blocking(move || {
+        let result = for_each_animation_frame(format, &data, |frame| {
+            sender.blocking_send(Ok(frame.clone())).map_err(|_| {
+                ImageHardenError::ValidationFailure("Frame stream was dropped".to_string())
+            })
+        });
+        if let Err(e) = result {
+            // Nobody to report to when the consumer has gone away
+            let _ = sender.blocking_send(Err(e));
+        }
+    });
+    FrameStream { frames }
+}
+
 /// Report which formats are available in the current build based on feature
 /// flags. Useful for capability advertisement in parent applications.
 pub fn supported_formats() -> Vec<&'static str> {
     let mut formats = vec![
-        "png", "jpeg", "gif", "webp", "heif", "svg", "mp3", "vorbis", "flac", "video",
+        "png", "jpeg", "gif", "webp", "heif", "svg", "pnm", "bmp", "ico", "mp3", "vorbis", "flac",
+        "video",
     ];
 
     #[cfg(feature = "avif")]
@@ -157,3 +963,626 @@ pub fn validate_metadata(data: &[u8]) -> Result<(), ImageHardenError> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::DECODED_BYTES_TOTAL;
+    use crate::test_support;
+
+    #[test]
+    fn test_decode_with_metrics_counts_output_bytes() {
+        let png = test_support::png_rgba(8, 4, [10, 20, 30, 255]);
+        let before = DECODED_BYTES_TOTAL.with_label_values(&["png"]).get();
+
+        let decoded = HardenedDecoder::decode_with_metrics(
+            MediaFormat::Png,
+            &png,
+            &DecoderOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(decoded.byte_len(), 8 * 4 * 4);
+
+        let after = DECODED_BYTES_TOTAL.with_label_values(&["png"]).get();
+        assert!(after - before >= (8 * 4 * 4) as f64);
+    }
+
+    #[test]
+    fn test_min_dimension_rejects_tracking_pixel() {
+        let pixel = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff\
+            ,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+        let options = DecoderOptions::default().with_limits(Limits {
+            min_dimension: 2,
+            ..Limits::balanced()
+        });
+        assert!(matches!(
+            HardenedDecoder::decode_with_options(MediaFormat::Gif, pixel, &options),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+
+        // Off by default; larger images pass the check
+        let png = test_support::png_rgba(1, 1, [0, 0, 0, 0]);
+        assert!(HardenedDecoder::decode(MediaFormat::Png, &png).is_ok());
+        let png = test_support::png_rgba(2, 2, [0, 0, 0, 0]);
+        assert!(HardenedDecoder::decode_with_options(MediaFormat::Png, &png, &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_aspect_ratio_rejects_sliver() {
+        let sliver = test_support::png(100_000, 1, 8, 0, &vec![0u8; 100_000], &[]);
+        assert!(matches!(
+            HardenedDecoder::decode(MediaFormat::Png, &sliver),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+
+        let normal = test_support::png_rgba(64, 16, [1, 2, 3, 4]);
+        assert!(HardenedDecoder::decode(MediaFormat::Png, &normal).is_ok());
+
+        // A tighter policy rejects what the default accepts
+        let options = DecoderOptions::default().with_limits(Limits {
+            max_aspect_ratio: 2,
+            ..Limits::balanced()
+        });
+        assert!(HardenedDecoder::decode_with_options(MediaFormat::Png, &normal, &options).is_err());
+    }
+
+    #[test]
+    fn test_zero_width_header_rejected() {
+        let zero_width = test_support::png(0, 1, 8, 0, &[], &[]);
+        assert!(matches!(
+            HardenedDecoder::decode(MediaFormat::Png, &zero_width),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_concatenated_images_rejected() {
+        let mut pngs = test_support::png_rgba(2, 2, [1, 2, 3, 255]);
+        pngs.extend_from_slice(&test_support::png_rgba(2, 2, [9, 9, 9, 255]));
+        assert!(matches!(
+            HardenedDecoder::decode(MediaFormat::Png, &pngs),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+
+        let mut jpegs = test_support::JPEG_RGB_8X8.to_vec();
+        jpegs.extend_from_slice(&test_support::JPEG_RGB_8X8);
+        assert!(matches!(
+            HardenedDecoder::decode(MediaFormat::Jpeg, &jpegs),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+
+        let options = DecoderOptions::default().with_limits(Limits {
+            reject_concatenated_images: false,
+            ..Limits::balanced()
+        });
+        assert!(HardenedDecoder::decode_with_options(MediaFormat::Png, &pngs, &options).is_ok());
+        assert!(HardenedDecoder::decode_with_options(MediaFormat::Jpeg, &jpegs, &options).is_ok());
+    }
+
+    #[test]
+    fn test_paranoid_limits_reject_animation() {
+        let palette = [[0, 0, 0], [255, 0, 0]];
+        let frames = [
+            test_support::TestGifFrame::new(0, 0, 2, 2, vec![0; 4]),
+            test_support::TestGifFrame::new(0, 0, 2, 2, vec![1; 4]),
+        ];
+        let animated = test_support::gif(2, 2, &palette, &frames);
+
+        let balanced = DecoderOptions::default().with_limits(Limits::balanced());
+        assert!(
+            HardenedDecoder::decode_with_options(MediaFormat::Gif, &animated, &balanced).is_ok()
+        );
+
+        let paranoid = DecoderOptions::default().with_limits(Limits::paranoid());
+        assert!(matches!(
+            HardenedDecoder::decode_with_options(MediaFormat::Gif, &animated, &paranoid),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+
+        // A still image of the same size passes both
+        let still = test_support::gif(2, 2, &palette, &frames[..1]);
+        assert!(HardenedDecoder::decode_with_options(MediaFormat::Gif, &still, &paranoid).is_ok());
+
+        // Paranoid also refuses trailing bytes and oversized headers
+        let mut trailing = test_support::png_rgba(4, 4, [1, 2, 3, 255]);
+        trailing.extend_from_slice(b"junk");
+        assert!(
+            HardenedDecoder::decode_with_options(MediaFormat::Png, &trailing, &balanced).is_ok()
+        );
+        assert!(
+            HardenedDecoder::decode_with_options(MediaFormat::Png, &trailing, &paranoid).is_err()
+        );
+        let wide = test_support::png_rgba(4096, 2, [0, 0, 0, 255]);
+        assert!(matches!(
+            HardenedDecoder::decode_with_options(MediaFormat::Png, &wide, &paranoid),
+            Err(ImageHardenError::ResourceExhausted(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_color_rejects_risky_layouts() {
+        let strict = DecoderOptions::default().with_limits(Limits {
+            strict_color: true,
+            ..Limits::balanced()
+        });
+        let normal = test_support::png_rgba(2, 2, [9, 8, 7, 255]);
+        assert!(HardenedDecoder::decode_with_options(MediaFormat::Png, &normal, &strict).is_ok());
+        assert!(HardenedDecoder::decode_with_options(
+            MediaFormat::Jpeg,
+            &test_support::JPEG_RGB_8X8,
+            &strict
+        )
+        .is_ok());
+
+        // Out-of-spec 16-bit palette is refused before libpng sees it
+        let palette16 = test_support::png(1, 1, 16, 3, &[0, 0], &[]);
+        match HardenedDecoder::decode_with_options(MediaFormat::Png, &palette16, &strict) {
+            Err(ImageHardenError::ValidationFailure(msg)) => assert!(msg.contains("bit depth 16")),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let gray2 = test_support::png(4, 1, 2, 0, &[0], &[]);
+        assert!(matches!(
+            HardenedDecoder::decode_with_options(MediaFormat::Png, &gray2, &strict),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+
+        // 4x1 luma sampling
+        let mut jpeg = test_support::JPEG_RGB_8X8.to_vec();
+        let sof = jpeg.windows(2).position(|w| w == [0xFF, 0xC0]).unwrap();
+        jpeg[sof + 11] = 0x41;
+        assert!(matches!(
+            HardenedDecoder::decode_with_options(MediaFormat::Jpeg, &jpeg, &strict),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_with_stats_png() {
+        let png = test_support::png_rgba(16, 8, [1, 2, 3, 4]);
+        let (decoded, stats) = HardenedDecoder::decode_with_stats(MediaFormat::Png, &png).unwrap();
+        assert_eq!(stats.input_bytes, png.len());
+        assert_eq!(stats.output_bytes, 16 * 8 * 4);
+        assert_eq!(stats.output_bytes, decoded.byte_len());
+        assert!(stats.peak_bytes_estimate > stats.output_bytes);
+        assert!(stats.peak_bytes_estimate < stats.output_bytes + 1024 * 1024);
+        assert!(stats.wall_time > Duration::ZERO);
+
+        assert!(HardenedDecoder::decode_with_stats(MediaFormat::Png, b"not a png").is_err());
+    }
+
+    #[test]
+    fn test_channel_order_bgra_swaps_red_and_blue() {
+        let png = test_support::png_rgba(2, 1, [10, 20, 30, 40]);
+        let decode = |order| {
+            let options = DecoderOptions::default().with_channel_order(order);
+            match HardenedDecoder::decode_with_options(MediaFormat::Png, &png, &options).unwrap() {
+                DecodedMedia::Image(pixels) => pixels,
+                _ => panic!("expected image output"),
+            }
+        };
+
+        assert_eq!(decode(ChannelOrder::Rgba), [10, 20, 30, 40, 10, 20, 30, 40]);
+        assert_eq!(decode(ChannelOrder::Bgra), [30, 20, 10, 40, 30, 20, 10, 40]);
+        assert_eq!(decode(ChannelOrder::Argb), [40, 10, 20, 30, 40, 10, 20, 30]);
+
+        // 16-bit samples move whole, never byte-swapped within a sample
+        let mut wide = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        reorder_channels(&mut wide, 4, 2, ChannelOrder::Bgra);
+        assert_eq!(wide, [5, 6, 3, 4, 1, 2, 7, 8]);
+        reorder_channels(&mut wide, 4, 2, ChannelOrder::Argb);
+        assert_eq!(wide, [7, 8, 5, 6, 3, 4, 1, 2]);
+
+        // Decoders without a swizzle refuse anything but RGBA
+        let options = DecoderOptions::default().with_channel_order(ChannelOrder::Bgra);
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1"/>"#;
+        assert!(matches!(
+            HardenedDecoder::decode_with_options(MediaFormat::Svg, svg, &options),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_checked_claimed_vs_detected() {
+        let png = test_support::png_rgba(2, 2, [5, 6, 7, 255]);
+        let checked =
+            HardenedDecoder::decode_checked(MediaFormat::Png, &png, &DecoderOptions::default())
+                .unwrap();
+        assert_eq!(checked.claimed, MediaFormat::Png);
+        assert_eq!(checked.detected, Some(MediaFormat::Png));
+        assert!(!checked.mismatched());
+
+        // A PNG uploaded as BMP decodes as PNG and is counted
+        let counter = crate::metrics::SUSPICIOUS_PATTERNS_TOTAL
+            .with_label_values(&["extension_mismatch", "bmp"]);
+        let before = counter.get();
+        let checked =
+            HardenedDecoder::decode_checked(MediaFormat::Bmp, &png, &DecoderOptions::default())
+                .unwrap();
+        assert!(checked.mismatched());
+        assert_eq!(checked.detected, Some(MediaFormat::Png));
+        assert_eq!(checked.media.byte_len(), 2 * 2 * 4);
+        assert_eq!(counter.get(), before + 1.0);
+
+        let strict = DecoderOptions::default().with_trust(FormatTrust::Reject);
+        assert!(matches!(
+            HardenedDecoder::decode_checked(MediaFormat::Bmp, &png, &strict),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+        assert_eq!(counter.get(), before + 2.0);
+
+        // Nothing recognizable: decoded as claimed, which fails here
+        assert!(HardenedDecoder::decode_checked(MediaFormat::Png, b"????", &strict).is_err());
+    }
+
+    #[test]
+    fn test_allowed_formats() {
+        let png_only = DecoderOptions::default().with_allowed_formats([MediaFormat::Png]);
+        let png = test_support::png_rgba(2, 2, [5, 6, 7, 255]);
+        assert!(HardenedDecoder::decode_checked(MediaFormat::Png, &png, &png_only).is_ok());
+
+        let jpeg = &test_support::JPEG_RGB_8X8;
+        assert!(matches!(
+            HardenedDecoder::decode_checked(MediaFormat::Jpeg, jpeg, &png_only),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+        // Claiming PNG does not help; the detected format is checked
+        assert!(matches!(
+            HardenedDecoder::decode_checked(MediaFormat::Png, jpeg, &png_only),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+        assert!(HardenedDecoder::decode_checked(
+            MediaFormat::Jpeg,
+            jpeg,
+            &DecoderOptions::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_detect_format_by_magic() {
+        let bmp_header = [&b"BM"[..], &[0; 12], &40u32.to_le_bytes()].concat();
+        let ico_header = [&[0, 0, 1, 0, 1, 0][..], &[16, 16, 0, 0], &[0; 12]].concat();
+        let cases: [(&[u8], Option<MediaFormat>); 11] = [
+            (
+                &test_support::png_rgba(1, 1, [0; 4]),
+                Some(MediaFormat::Png),
+            ),
+            (&test_support::JPEG_RGB_8X8, Some(MediaFormat::Jpeg)),
+            (&test_support::WEBP_LOSSY_8X8, Some(MediaFormat::WebP)),
+            (b"P6 1 1 255\n\0\0\0", Some(MediaFormat::Pnm)),
+            (&bmp_header, Some(MediaFormat::Bmp)),
+            (b"BM with no info header", None),
+            (&ico_header, Some(MediaFormat::Ico)),
+            (b"\n<?xml version='1.0'?>\n<svg/>", Some(MediaFormat::Svg)),
+            (
+                include_bytes!("../../mov_bbb.mp4"),
+                Some(MediaFormat::VideoContainer),
+            ),
+            (b"P9 not netpbm", None),
+            (b"", None),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(detect_format(data), expected);
+        }
+    }
+
+    #[test]
+    fn test_decode_pnm_via_api() {
+        let ppm = b"P3\n2 1\n255\n255 0 0 0 0 255\n";
+        assert_eq!(detect_format(ppm), Some(MediaFormat::Pnm));
+        match HardenedDecoder::decode(MediaFormat::Pnm, ppm).unwrap() {
+            DecodedMedia::Image(rgba) => assert_eq!(rgba, [255, 0, 0, 255, 0, 0, 255, 255]),
+            other => panic!("unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_with_metrics_releases_in_flight_gauge() {
+        let _lock = test_support::lock_metrics();
+        let gauge = crate::metrics::IN_FLIGHT_DECODES.with_label_values(&["flac"]);
+        let before = gauge.get();
+        let result = HardenedDecoder::decode_with_metrics(
+            MediaFormat::AudioFlac,
+            b"not a flac file",
+            &DecoderOptions::default(),
+        );
+        assert!(result.is_err());
+        assert_eq!(gauge.get(), before);
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod async_tests {
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    async fn test_decode_async_matches_blocking_decode() {
+        let png = test_support::png_rgba(8, 4, [10, 20, 30, 255]);
+        let decoded = HardenedDecoder::decode_async(MediaFormat::Png, png.clone())
+            .await
+            .unwrap();
+        let blocking = HardenedDecoder::decode(MediaFormat::Png, &png).unwrap();
+        match (decoded, blocking) {
+            (DecodedMedia::Image(a), DecodedMedia::Image(b)) => assert_eq!(a, b),
+            _ => panic!("expected image output"),
+        }
+
+        assert!(matches!(
+            HardenedDecoder::decode_async(MediaFormat::Png, b"not a png".to_vec()).await,
+            Err(ImageHardenError::PngError(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_decode_async_keeps_runtime_responsive() {
+        // The decode runs off the runtime thread, so other tasks still progress
+        let png = test_support::png_rgba(256, 256, [1, 2, 3, 4]);
+        let decode = tokio::spawn(HardenedDecoder::decode_async(MediaFormat::Png, png));
+        let ticker = tokio::spawn(async { 42 });
+        assert_eq!(ticker.await.unwrap(), 42);
+        assert!(decode.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_decode_async_limited_waits_for_permit() {
+        use std::sync::Arc;
+        use tokio::sync::Semaphore;
+
+        let png = test_support::png_rgba(8, 8, [1, 2, 3, 4]);
+        let limiter = Arc::new(Semaphore::new(1));
+
+        // With the only permit held elsewhere the decode cannot start
+        let held = limiter.clone().acquire_owned().await.unwrap();
+        let blocked = tokio::spawn(HardenedDecoder::decode_async_limited(
+            MediaFormat::Png,
+            png.clone(),
+            DecoderOptions::default(),
+            limiter.clone(),
+        ));
+        for _ in 0..100 {
+            tokio::task::yield_now().await;
+        }
+        assert!(!blocked.is_finished());
+        drop(held);
+        assert!(blocked.await.unwrap().is_ok());
+
+        // Several decodes through two permits never run more than two at
+        // once, all finish, and return their permits
+        LIMITED_DECODES_PEAK.store(0, std::sync::atomic::Ordering::SeqCst);
+        let limiter = Arc::new(Semaphore::new(2));
+        let large = test_support::png_rgba(512, 512, [1, 2, 3, 4]);
+        let decodes: Vec<_> = (0..6)
+            .map(|_| {
+                tokio::spawn(HardenedDecoder::decode_async_limited(
+                    MediaFormat::Png,
+                    large.clone(),
+                    DecoderOptions::default(),
+                    limiter.clone(),
+                ))
+            })
+            .collect();
+        for decode in decodes {
+            assert!(decode.await.unwrap().is_ok());
+        }
+        let peak = LIMITED_DECODES_PEAK.load(std::sync::atomic::Ordering::SeqCst);
+        assert!((1..=2).contains(&peak), "peak {}", peak);
+        assert_eq!(limiter.available_permits(), 2);
+
+        limiter.close();
+        assert!(matches!(
+            HardenedDecoder::decode_async_limited(
+                MediaFormat::Png,
+                png,
+                DecoderOptions::default(),
+                limiter
+            )
+            .await,
+            Err(ImageHardenError::ResourceExhausted(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_frame_stream_matches_eager_decode() {
+        use futures_util::StreamExt;
+
+        let palette = [[255, 0, 0], [0, 255, 0], [0, 0, 255]];
+        let frames: Vec<_> = (0..3)
+            .map(|i| test_support::TestGifFrame::new(i as u16, 0, 1, 1, vec![i]))
+            .collect();
+        let gif = test_support::gif(3, 1, &palette, &frames);
+
+        let streamed: Vec<_> = decode_frames_stream(MediaFormat::Gif, gif.clone())
+            .collect()
+            .await;
+        let eager = crate::decode_animation_frames(MediaFormat::Gif, &gif).unwrap();
+        assert_eq!(streamed.len(), eager.len());
+        for (streamed, eager) in streamed.into_iter().zip(&eager) {
+            let streamed = streamed.unwrap();
+            assert_eq!(
+                (streamed.width, streamed.height, &streamed.data),
+                (eager.width, eager.height, &eager.data)
+            );
+        }
+
+        // APNG frames stream the same way
+        let apng_frames: Vec<_> = (0..2)
+            .map(|left| test_support::TestApngFrame {
+                left,
+                top: 0,
+                width: 1,
+                height: 1,
+                rgba: [0, 0, 255, 255],
+                dispose_op: 0,
+                blend_op: 0,
+            })
+            .collect();
+        let apng = test_support::apng(2, 1, &apng_frames);
+        let streamed: Vec<_> = decode_frames_stream(MediaFormat::Png, apng).collect().await;
+        assert_eq!(streamed.len(), 2);
+        let last = streamed[1].as_ref().unwrap();
+        assert_eq!(last.data, [0, 0, 255, 255, 0, 0, 255, 255]);
+
+        // GIF frames are expanded one at a time: a corrupt last frame fails
+        // the stream only after the good frames were sent, and no more than
+        // one finished frame waits in the channel
+        let mut frames: Vec<_> = (0..3)
+            .map(|i| test_support::TestGifFrame::new(0, 0, 1, 1, vec![i % 2]))
+            .collect();
+        // Index 3 is past the 2-color table
+        frames.push(test_support::TestGifFrame::new(0, 0, 1, 1, vec![3]));
+        let corrupt = test_support::gif(1, 1, &[[0, 0, 0], [255, 255, 255]], &frames);
+        assert!(crate::decode_animation_frames(MediaFormat::Gif, &corrupt).is_err());
+
+        let mut stream = decode_frames_stream(MediaFormat::Gif, corrupt.clone());
+        assert!(matches!(stream.next().await, Some(Ok(_))));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(stream.frames.len() <= 1);
+        drop(stream);
+
+        let results: Vec<_> = decode_frames_stream(MediaFormat::Gif, corrupt)
+            .collect()
+            .await;
+        assert_eq!(results.len(), 4);
+        assert!(results[..3].iter().all(Result::is_ok));
+        assert!(results[3].is_err());
+
+        // Errors end the stream
+        let mut failed = decode_frames_stream(MediaFormat::Gif, b"GIF89a".to_vec());
+        assert!(matches!(failed.next().await, Some(Err(_))));
+        assert!(failed.next().await.is_none());
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod otel_tests {
+    use super::*;
+    use crate::test_support;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    /// Collects the fields recorded on `media.decode` spans and decode log events
+    #[derive(Clone, Default)]
+    struct SpanCapture {
+        span: Arc<Mutex<HashMap<String, String>>>,
+        event: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for SpanCapture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            if attrs.metadata().name() == "media.decode" {
+                attrs.record(&mut FieldVisitor(&mut self.span.lock().unwrap()));
+            }
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut FieldVisitor(&mut self.span.lock().unwrap()));
+        }
+
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            if event.metadata().target() == "image_harden::decode" {
+                event.record(&mut FieldVisitor(&mut self.event.lock().unwrap()));
+            }
+        }
+    }
+
+    fn capture_all<F: FnOnce()>(f: F) -> (HashMap<String, String>, HashMap<String, String>) {
+        let layer = SpanCapture::default();
+        let subscriber = tracing_subscriber::registry().with(layer.clone());
+        tracing::subscriber::with_default(subscriber, f);
+        let span = layer.span.lock().unwrap().clone();
+        let event = layer.event.lock().unwrap().clone();
+        (span, event)
+    }
+
+    fn capture<F: FnOnce()>(f: F) -> HashMap<String, String> {
+        capture_all(f).0
+    }
+
+    #[test]
+    fn test_decode_span_carries_sizes() {
+        let png = test_support::png_rgba(4, 2, [1, 2, 3, 4]);
+        let fields = capture(|| {
+            HardenedDecoder::decode_with_metrics(
+                MediaFormat::Png,
+                &png,
+                &DecoderOptions::default(),
+            )
+            .unwrap();
+        });
+
+        assert_eq!(fields["media.format"], "png");
+        assert_eq!(fields["media.input_bytes"], png.len().to_string());
+        assert_eq!(fields["media.output_bytes"], (4 * 2 * 4).to_string());
+        assert!(!fields.contains_key("media.error_kind"));
+    }
+
+    #[test]
+    fn test_decode_span_records_error_kind() {
+        let _lock = test_support::lock_metrics();
+        let fields = capture(|| {
+            let _ = HardenedDecoder::decode_with_metrics(
+                MediaFormat::AudioFlac,
+                b"not a flac file",
+                &DecoderOptions::default(),
+            );
+        });
+
+        assert_eq!(fields["media.format"], "flac");
+        assert_eq!(fields["media.error_kind"], "flac_error");
+        assert!(!fields.contains_key("media.output_bytes"));
+    }
+
+    #[test]
+    fn test_decode_log_uses_generated_id() {
+        let png = test_support::png_rgba(2, 2, [0, 0, 0, 255]);
+        let (span, event) = capture_all(|| {
+            HardenedDecoder::decode_with_metrics(
+                MediaFormat::Png,
+                &png,
+                &DecoderOptions::default(),
+            )
+            .unwrap();
+        });
+
+        let id = &event["decode.id"];
+        assert!(uuid::Uuid::parse_str(id).is_ok());
+        assert_eq!(&span["decode.id"], id);
+        assert_eq!(event["outcome"], "success");
+        assert_eq!(event["media.format"], "png");
+        assert_eq!(event["media.input_bytes"], png.len().to_string());
+        assert_eq!(event["media.output_bytes"], "16");
+    }
+
+    #[test]
+    fn test_decode_log_uses_caller_trace_id() {
+        let _lock = test_support::lock_metrics();
+        let options = DecoderOptions::default().with_trace_id("req-42\nforged log line");
+        let (_, event) = capture_all(|| {
+            let _ =
+                HardenedDecoder::decode_with_metrics(MediaFormat::AudioFlac, b"bogus", &options);
+        });
+
+        assert_eq!(event["decode.id"], "req-42forgedlogline");
+        assert_eq!(event["outcome"], "failure");
+        assert_eq!(event["error_kind"], "flac_error");
+        assert_eq!(event["media.output_bytes"], "0");
+    }
+}
@@ -0,0 +1,272 @@
+///! Audio feature extraction on decoded output
+///!
+///! Provides mel spectrograms for downstream classifiers so they do not need
+///! to reimplement FFT/filterbank code. Operates only on already-decoded,
+///! limit-checked `AudioData`.
+///!
+///! Security measures:
+///! - Parameter validation (FFT size, hop, band count)
+///! - FFT size and mel band caps to bound per-frame work and memory
+///! - Output size budget checked before the spectrogram is allocated
+///! - Sparse filterbank: only each band's non-zero bins are stored
+///! - Pure Rust FFT (rustfft)
+use crate::{AudioData, ImageHardenError};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+/// Maximum FFT window size (samples)
+const MAX_FFT_SIZE: usize = 65536;
+
+/// Maximum number of mel bands
+const MAX_MEL_BANDS: usize = 512;
+
+/// Maximum spectrogram values (frames * bands), 64 MB of f32
+const MAX_SPECTROGRAM_VALUES: usize = 16 * 1024 * 1024;
+
+/// Convert frequency in Hz to the HTK mel scale
+fn hz_to_mel(hz: f64) -> f64 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+/// Convert a mel-scale value back to Hz
+fn mel_to_hz(mel: f64) -> f64 {
+    700.0 * (10f64.powf(mel / 2595.0) - 1.0)
+}
+
+/// Frequency edges (Hz) for `n_mels` triangular bands between 0 and Nyquist
+///
+/// Band `m` spans `edges[m]..edges[m + 2]` and peaks at `edges[m + 1]`.
+fn mel_band_edges(sample_rate: u32, n_mels: usize) -> Vec<f64> {
+    let max_mel = hz_to_mel(sample_rate as f64 / 2.0);
+    (0..n_mels + 2)
+        .map(|i| mel_to_hz(max_mel * i as f64 / (n_mels + 1) as f64))
+        .collect()
+}
+
+/// One triangular mel band: weights for the FFT bins `first..first + weights.len()`
+struct MelBand {
+    first: usize,
+    weights: Vec<f32>,
+}
+
+impl MelBand {
+    fn apply(&self, power: &[f32]) -> f32 {
+        self.weights
+            .iter()
+            .zip(&power[self.first..])
+            .map(|(w, p)| w * p)
+            .sum()
+    }
+}
+
+/// Build the triangular mel filterbank over `n_fft / 2 + 1` FFT bins
+///
+/// Each band keeps only the bins inside its triangle, so the bank holds
+/// about two weights per bin instead of `n_mels` per bin.
+fn mel_filterbank(sample_rate: u32, n_fft: usize, n_mels: usize) -> Vec<MelBand> {
+    let n_bins = n_fft / 2 + 1;
+    let edges = mel_band_edges(sample_rate, n_mels);
+    let bin_hz = sample_rate as f64 / n_fft as f64;
+
+    (0..n_mels)
+        .map(|m| {
+            let (lower, center, upper) = (edges[m], edges[m + 1], edges[m + 2]);
+            // Bins strictly above `lower` and strictly below `upper`
+            let first = ((lower / bin_hz).floor() as usize + 1).min(n_bins);
+            let end = ((upper / bin_hz).ceil() as usize).clamp(first, n_bins);
+            let weights = (first..end)
+                .map(|bin| {
+                    let hz = bin as f64 * bin_hz;
+                    let weight = if hz > lower && hz <= center {
+                        (hz - lower) / (center - lower)
+                    } else if hz > center && hz < upper {
+                        (upper - hz) / (upper - center)
+                    } else {
+                        0.0
+                    };
+                    weight as f32
+                })
+                .collect();
+            MelBand { first, weights }
+        })
+        .collect()
+}
+
+impl AudioData {
+    /// Compute a mel-scaled power spectrogram
+    ///
+    /// Channels are downmixed to mono and each frame is Hann-windowed before
+    /// the FFT. Returns one `n_mels`-long row per frame. Inputs shorter than
+    /// `n_fft` are zero-padded to a single frame.
+    pub fn mel_spectrogram(
+        &self,
+        n_fft: usize,
+        hop: usize,
+        n_mels: usize,
+    ) -> Result<Vec<Vec<f32>>, ImageHardenError> {
+        if n_fft < 2 || n_fft > MAX_FFT_SIZE {
+            return Err(ImageHardenError::AudioError(format!(
+                "Invalid FFT size: {} (must be 2..={})",
+                n_fft, MAX_FFT_SIZE
+            )));
+        }
+        if hop == 0 {
+            return Err(ImageHardenError::AudioError(
+                "Hop length must be non-zero".to_string(),
+            ));
+        }
+        let max_mels = MAX_MEL_BANDS.min(n_fft / 2 + 1);
+        if n_mels == 0 || n_mels > max_mels {
+            return Err(ImageHardenError::AudioError(format!(
+                "Invalid mel band count: {} (must be 1..={})",
+                n_mels, max_mels
+            )));
+        }
+        if self.sample_rate == 0 || self.channels == 0 {
+            return Err(ImageHardenError::AudioError(
+                "Invalid audio parameters".to_string(),
+            ));
+        }
+
+        // Budget the output before any per-sample allocation
+        let channels = self.channels as usize;
+        let mono_len = self.samples.len().div_ceil(channels).max(n_fft);
+        let frame_count = 1 + (mono_len - n_fft) / hop;
+        if frame_count
+            .checked_mul(n_mels)
+            .is_none_or(|values| values > MAX_SPECTROGRAM_VALUES)
+        {
+            return Err(ImageHardenError::ResourceExhausted(format!(
+                "Spectrogram of {} frames x {} bands exceeds {} values",
+                frame_count, n_mels, MAX_SPECTROGRAM_VALUES
+            )));
+        }
+
+        // Downmix interleaved samples to mono f32
+        let mut mono: Vec<f32> = self
+            .samples
+            .chunks(channels)
+            .map(|frame| frame.iter().map(|&s| s as f32 / 32768.0).sum::<f32>() / channels as f32)
+            .collect();
+        if mono.len() < n_fft {
+            mono.resize(n_fft, 0.0);
+        }
+
+        let window: Vec<f32> = (0..n_fft)
+            .map(|i| {
+                let phase = 2.0 * std::f64::consts::PI * i as f64 / n_fft as f64;
+                (0.5 - 0.5 * phase.cos()) as f32
+            })
+            .collect();
+        let filterbank = mel_filterbank(self.sample_rate, n_fft, n_mels);
+
+        let mut planner = FftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(n_fft);
+        let mut buffer = vec![Complex::new(0.0f32, 0.0f32); n_fft];
+
+        let mut spectrogram = Vec::with_capacity(frame_count);
+
+        for frame in 0..frame_count {
+            let start = frame * hop;
+            for (i, slot) in buffer.iter_mut().enumerate() {
+                *slot = Complex::new(mono[start + i] * window[i], 0.0);
+            }
+            fft.process(&mut buffer);
+
+            let power: Vec<f32> = buffer[..n_fft / 2 + 1]
+                .iter()
+                .map(|c| c.norm_sqr())
+                .collect();
+            let row = filterbank.iter().map(|band| band.apply(&power)).collect();
+            spectrogram.push(row);
+        }
+
+        Ok(spectrogram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, sample_rate: u32, len: usize) -> AudioData {
+        let samples = (0..len)
+            .map(|i| {
+                let t = i as f64 / sample_rate as f64;
+                ((2.0 * std::f64::consts::PI * freq * t).sin() * 16000.0) as i16
+            })
+            .collect::<Vec<_>>();
+        AudioData {
+            duration_secs: len as f64 / sample_rate as f64,
+            samples,
+            sample_rate,
+            channels: 1,
+        }
+    }
+
+    #[test]
+    fn test_sine_peaks_in_matching_band() {
+        let audio = sine(1000.0, 16000, 16000);
+        let spectrogram = audio.mel_spectrogram(512, 256, 40).unwrap();
+        assert_eq!(spectrogram.len(), 1 + (16000 - 512) / 256);
+
+        let row = &spectrogram[10];
+        let peak = row
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .map(|(i, _)| i)
+            .unwrap();
+
+        let edges = mel_band_edges(16000, 40);
+        assert!(edges[peak] < 1000.0 && 1000.0 < edges[peak + 2]);
+    }
+
+    #[test]
+    fn test_short_input_is_zero_padded() {
+        let audio = sine(440.0, 8000, 100);
+        let spectrogram = audio.mel_spectrogram(256, 128, 16).unwrap();
+        assert_eq!(spectrogram.len(), 1);
+        assert_eq!(spectrogram[0].len(), 16);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let audio = sine(440.0, 8000, 1000);
+        assert!(audio.mel_spectrogram(1, 128, 16).is_err());
+        assert!(audio.mel_spectrogram(256, 0, 16).is_err());
+        assert!(audio.mel_spectrogram(256, 128, 0).is_err());
+        assert!(audio.mel_spectrogram(256, 128, 200).is_err());
+        assert!(audio.mel_spectrogram(MAX_FFT_SIZE * 2, 128, 16).is_err());
+        assert!(audio
+            .mel_spectrogram(MAX_FFT_SIZE, 128, MAX_MEL_BANDS + 1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_output_budget_checked_before_allocation() {
+        // 40k frames of 512 bands is over the 16M-value budget
+        let audio = sine(440.0, 8000, 41_000);
+        assert!(matches!(
+            audio.mel_spectrogram(1024, 1, MAX_MEL_BANDS),
+            Err(ImageHardenError::ResourceExhausted(_))
+        ));
+        assert!(audio.mel_spectrogram(1024, 64, MAX_MEL_BANDS).is_ok());
+    }
+
+    #[test]
+    fn test_sparse_filterbank_matches_dense_triangles() {
+        let (sample_rate, n_fft, n_mels) = (16000, 512, 40);
+        let edges = mel_band_edges(sample_rate, n_mels);
+        let bin_hz = sample_rate as f64 / n_fft as f64;
+        let bank = mel_filterbank(sample_rate, n_fft, n_mels);
+        for (m, band) in bank.iter().enumerate() {
+            for bin in 0..n_fft / 2 + 1 {
+                let hz = bin as f64 * bin_hz;
+                let inside = hz > edges[m] && hz < edges[m + 2];
+                let stored = (band.first..band.first + band.weights.len()).contains(&bin);
+                assert!(!inside || stored, "band {} drops bin {}", m, bin);
+            }
+        }
+    }
+}
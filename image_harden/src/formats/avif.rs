@@ -15,8 +15,8 @@ const MAX_DIMENSION: u32 = 16384;
 /// Maximum allowed file size (256 MB)
 const MAX_FILE_SIZE: usize = 256 * 1024 * 1024;
 
-/// AVIF magic bytes (ftyp box with avif brand)
-const AVIF_MAGIC: &[u8] = b"ftyp";
+/// ftyp brands marking an AVIF still image or image sequence
+const AVIF_BRANDS: [&[u8; 4]; 2] = [b"avif", b"avis"];
 
 /// Hardened AVIF decoder configuration
 #[derive(Debug, Clone)]
@@ -71,18 +71,17 @@ pub fn decode_avif_with_config(
         ));
     }
 
-    // Check for ftyp box (AVIF is based on ISO Base Media File Format)
-    let has_ftyp = data
-        .windows(4)
-        .take(20) // Check first 20 bytes
-        .any(|window| window == AVIF_MAGIC);
-
-    if !has_ftyp {
+    // Leading ftyp box listing an AVIF brand (AVIF is based on ISO Base
+    // Media File Format)
+    if !crate::iso_bmff::has_brand(data, &AVIF_BRANDS) {
         return Err(ImageHardenError::AvifError(
             "Invalid AVIF magic bytes".to_string(),
         ));
     }
 
+    // Box nesting depth (meta/iprp/ipco chains) before any box parsing
+    crate::check_box_nesting(data)?;
+
     // TODO: Implement actual libavif FFI decoding
     // For now, return placeholder
     // In production, this would:
@@ -113,12 +112,7 @@ pub fn validate_avif(data: &[u8]) -> Result<(), ImageHardenError> {
         ));
     }
 
-    let has_ftyp = data
-        .windows(4)
-        .take(20)
-        .any(|window| window == AVIF_MAGIC);
-
-    if !has_ftyp {
+    if !crate::iso_bmff::has_brand(data, &AVIF_BRANDS) {
         return Err(ImageHardenError::AvifError(
             "Invalid AVIF magic bytes".to_string(),
         ));
@@ -148,4 +142,19 @@ mod tests {
         let result = decode_avif(&[0u8; 20]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_brand_from_ftyp_box() {
+        let ftyp = |brands: &[u8]| {
+            let mut data = ((8 + brands.len()) as u32).to_be_bytes().to_vec();
+            data.extend_from_slice(b"ftyp");
+            data.extend_from_slice(brands);
+            data
+        };
+        assert!(validate_avif(&ftyp(b"mif1\0\0\0\0avif")).is_ok());
+        assert!(validate_avif(&ftyp(b"avis\0\0\0\0")).is_ok());
+        // "ftyp" in the first 20 bytes is not enough; the brand must be there
+        assert!(validate_avif(&ftyp(b"heic\0\0\0\0mif1")).is_err());
+        assert!(validate_avif(b"\0\0\0\x0cfreeftypavif").is_err());
+    }
 }
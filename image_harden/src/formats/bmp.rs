@@ -0,0 +1,549 @@
+///! BMP decoder with comprehensive hardening
+///!
+///! Security measures:
+///! - Pure Rust, no native library involved
+///! - Strict dimension limits (max 16384x16384) and the shared pixel budget
+///! - File size caps (max 256 MB)
+///! - Checked arithmetic for row stride and raster size
+///! - `bfOffBits`, palette, and `biSizeImage` validated against the file
+///!   before any pixel is read
+///! - RLE compression rejected in strict mode (default)
+///! - Fail-closed error handling
+use crate::{check_nonzero_dimensions, check_pixel_budget, DecodedImage, ImageHardenError};
+
+/// Maximum allowed BMP image dimensions
+const MAX_DIMENSION: u32 = 16384;
+
+/// Maximum allowed file size (256 MB)
+const MAX_FILE_SIZE: usize = 256 * 1024 * 1024;
+
+/// BITMAPFILEHEADER size
+const FILE_HEADER_LEN: usize = 14;
+
+/// Maximum palette entries for an 8-bit image
+const MAX_PALETTE_ENTRIES: u32 = 256;
+
+/// biCompression values
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_BITFIELDS: u32 = 3;
+
+/// Hardened BMP decoder configuration
+#[derive(Debug, Clone)]
+pub struct BmpDecoderConfig {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_file_size: usize,
+    pub strict_mode: bool,    // Reject RLE8 compression
+    pub reserved_alpha: bool, // Read the 4th byte of 32-bit BI_RGB pixels as alpha (ICO)
+}
+
+impl Default for BmpDecoderConfig {
+    fn default() -> Self {
+        Self {
+            max_width: MAX_DIMENSION,
+            max_height: MAX_DIMENSION,
+            max_file_size: MAX_FILE_SIZE,
+            strict_mode: true,
+            reserved_alpha: false,
+        }
+    }
+}
+
+/// BITMAPINFOHEADER sizes accepted (V1 through V5)
+const INFO_HEADER_SIZES: [usize; 5] = [40, 52, 56, 108, 124];
+
+/// True for a `BM` magic followed by a supported info header size
+pub fn is_bmp(data: &[u8]) -> bool {
+    data.len() >= FILE_HEADER_LEN + 4
+        && data.starts_with(b"BM")
+        && INFO_HEADER_SIZES
+            .contains(&(u32::from_le_bytes([data[14], data[15], data[16], data[17]]) as usize))
+}
+
+/// Decode a BMP image with hardening
+///
+/// Supports 8-bit palettized (uncompressed, or RLE8 outside strict mode),
+/// 24-bit, and 32-bit (BI_RGB or standard BGRA bitfields) images with
+/// BITMAPINFOHEADER or later headers.
+pub fn decode_bmp(data: &[u8]) -> Result<DecodedImage, ImageHardenError> {
+    decode_bmp_with_config(data, &BmpDecoderConfig::default())
+}
+
+fn bmp_error(msg: impl Into<String>) -> ImageHardenError {
+    ImageHardenError::BmpError(msg.into())
+}
+
+/// Decode BMP with custom configuration
+pub fn decode_bmp_with_config(
+    data: &[u8],
+    config: &BmpDecoderConfig,
+) -> Result<DecodedImage, ImageHardenError> {
+    if data.len() > config.max_file_size {
+        return Err(bmp_error(format!(
+            "File size {} exceeds maximum {}",
+            data.len(),
+            config.max_file_size
+        )));
+    }
+    if data.len() < FILE_HEADER_LEN + 40 || !data.starts_with(b"BM") {
+        return Err(bmp_error("Invalid BMP magic bytes or truncated header"));
+    }
+
+    let le16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let le32 = |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+
+    let off_bits = le32(10) as usize;
+    let header_size = le32(14) as usize;
+    if !INFO_HEADER_SIZES.contains(&header_size) {
+        return Err(bmp_error(format!(
+            "Unsupported BMP header size {}",
+            header_size
+        )));
+    }
+    let header_end = FILE_HEADER_LEN + header_size;
+    if header_end > data.len() {
+        return Err(bmp_error("Truncated BMP info header"));
+    }
+
+    let raw_width = le32(18) as i32;
+    let raw_height = le32(22) as i32;
+    let planes = le16(26);
+    let bit_count = le16(28);
+    let compression = le32(30);
+    let size_image = le32(34) as usize;
+    let colors_used = le32(46);
+
+    if planes != 1 {
+        return Err(bmp_error(format!("Invalid plane count {}", planes)));
+    }
+    if raw_width <= 0 {
+        return Err(bmp_error(format!("Invalid width {}", raw_width)));
+    }
+    let width = raw_width as u32;
+    // Negative height means rows are stored top-down
+    let top_down = raw_height < 0;
+    let height = raw_height.unsigned_abs();
+    check_nonzero_dimensions(width, height)?;
+    if width > config.max_width || height > config.max_height {
+        return Err(bmp_error(format!(
+            "Dimensions {}x{} exceed maximum {}x{}",
+            width, height, config.max_width, config.max_height
+        )));
+    }
+    let out_len = check_pixel_budget(width, height, 4)?;
+
+    match (bit_count, compression) {
+        (8, BI_RGB) | (24, BI_RGB) | (32, BI_RGB) | (32, BI_BITFIELDS) => {}
+        (8, BI_RLE8) if config.strict_mode => {
+            return Err(bmp_error("RLE8 compression rejected in strict mode"));
+        }
+        (8, BI_RLE8) if top_down => {
+            return Err(bmp_error("RLE8 bitmaps cannot be top-down"));
+        }
+        (8, BI_RLE8) => {}
+        _ => {
+            return Err(bmp_error(format!(
+                "Unsupported BMP format: {} bpp, compression {}",
+                bit_count, compression
+            )))
+        }
+    }
+
+    // Channel masks (32-bit bitfields) follow a 40-byte header, or sit
+    // inside V2+ headers at the same offset
+    let mut tables_end = header_end;
+    let mut has_alpha = compression == BI_RGB && bit_count == 32 && config.reserved_alpha;
+    if compression == BI_BITFIELDS {
+        if header_size == 40 {
+            tables_end += 12;
+        }
+        if tables_end > data.len() {
+            return Err(bmp_error("Truncated BMP bitfield masks"));
+        }
+        let (r, g, b) = (le32(54), le32(58), le32(62));
+        if (r, g, b) != (0x00FF_0000, 0x0000_FF00, 0x0000_00FF) {
+            return Err(bmp_error(format!(
+                "Unsupported bitfield masks {:08X}/{:08X}/{:08X}",
+                r, g, b
+            )));
+        }
+        // Alpha mask only exists in V3+ headers
+        has_alpha = header_size >= 56 && le32(66) == 0xFF00_0000;
+    }
+
+    let mut palette = Vec::new();
+    if bit_count == 8 {
+        let entries = if colors_used == 0 {
+            MAX_PALETTE_ENTRIES
+        } else {
+            colors_used
+        };
+        if entries > MAX_PALETTE_ENTRIES {
+            return Err(bmp_error(format!(
+                "Palette of {} entries exceeds {}",
+                entries, MAX_PALETTE_ENTRIES
+            )));
+        }
+        let palette_end = tables_end + entries as usize * 4;
+        if palette_end > off_bits || palette_end > data.len() {
+            return Err(bmp_error("BMP palette overlaps pixel data or file end"));
+        }
+        palette = data[tables_end..palette_end]
+            .chunks_exact(4)
+            .map(|bgrx| [bgrx[2], bgrx[1], bgrx[0]])
+            .collect();
+        tables_end = palette_end;
+    }
+
+    // bfOffBits must point past the headers and tables, inside the file
+    if off_bits < tables_end || off_bits > data.len() {
+        return Err(bmp_error(format!(
+            "Pixel data offset {} outside {}..={}",
+            off_bits,
+            tables_end,
+            data.len()
+        )));
+    }
+
+    let stride = (width as usize)
+        .checked_mul(bit_count as usize)
+        .and_then(|bits| bits.checked_add(31))
+        .map(|bits| bits / 32 * 4)
+        .ok_or_else(|| bmp_error("Row stride overflow"))?;
+
+    let rle_indices: Vec<u8>;
+    let pixels: &[u8] = if compression == BI_RLE8 {
+        // biSizeImage is the compressed size and is required for RLE
+        let end = off_bits
+            .checked_add(size_image)
+            .filter(|&end| size_image > 0 && end <= data.len())
+            .ok_or_else(|| bmp_error(format!("Invalid RLE8 data size {}", size_image)))?;
+        rle_indices = decode_rle8(&data[off_bits..end], width, height)?;
+        &rle_indices
+    } else {
+        let raster_len = stride
+            .checked_mul(height as usize)
+            .ok_or_else(|| bmp_error("Raster size overflow"))?;
+        // A nonzero biSizeImage that undercounts the raster is a classic
+        // overflow setup for decoders that allocate from it
+        if size_image != 0 && size_image < raster_len {
+            return Err(bmp_error(format!(
+                "biSizeImage {} smaller than the {}-byte raster",
+                size_image, raster_len
+            )));
+        }
+        let end = off_bits
+            .checked_add(raster_len)
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| {
+                bmp_error(format!(
+                    "Raster of {} bytes at offset {} runs past the file end",
+                    raster_len, off_bits
+                ))
+            })?;
+        &data[off_bits..end]
+    };
+
+    // RLE8 output is already unpadded, one index per pixel
+    let row_len = if compression == BI_RLE8 {
+        width as usize
+    } else {
+        stride
+    };
+    let mut rgba = vec![0u8; out_len];
+    let out_stride = width as usize * 4;
+    for (row_index, row) in pixels
+        .chunks_exact(row_len)
+        .take(height as usize)
+        .enumerate()
+    {
+        let y = if top_down {
+            row_index
+        } else {
+            height as usize - 1 - row_index
+        };
+        let out_row = &mut rgba[y * out_stride..(y + 1) * out_stride];
+        for (x, dst) in out_row.chunks_exact_mut(4).enumerate() {
+            let px = match bit_count {
+                8 => {
+                    // Indices past a short palette are rejected, not wrapped
+                    let [r, g, b] = *palette.get(row[x] as usize).ok_or_else(|| {
+                        bmp_error(format!("Palette index {} out of range", row[x]))
+                    })?;
+                    [r, g, b, 255]
+                }
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+                _ => {
+                    let bgra = &row[x * 4..x * 4 + 4];
+                    [
+                        bgra[2],
+                        bgra[1],
+                        bgra[0],
+                        if has_alpha { bgra[3] } else { 255 },
+                    ]
+                }
+            };
+            dst.copy_from_slice(&px);
+        }
+    }
+
+    Ok(DecodedImage {
+        data: rgba,
+        width,
+        height,
+        channels: 4,
+        bit_depth: 8,
+        had_alpha: has_alpha,
+        icc_profile: None,
+    })
+}
+
+/// Decode a packed DIB: info header, tables, and pixels with no
+/// BITMAPFILEHEADER in front, as embedded in ICO/CUR resources
+pub fn decode_dib_with_config(
+    dib: &[u8],
+    config: &BmpDecoderConfig,
+) -> Result<DecodedImage, ImageHardenError> {
+    if dib.len() > config.max_file_size.saturating_sub(FILE_HEADER_LEN) {
+        return Err(bmp_error(format!(
+            "DIB size {} exceeds maximum {}",
+            dib.len(),
+            config.max_file_size
+        )));
+    }
+    let off_bits = FILE_HEADER_LEN + dib_pixel_offset(dib)?;
+    let file_len = FILE_HEADER_LEN + dib.len();
+
+    let mut data = Vec::with_capacity(file_len);
+    data.extend_from_slice(b"BM");
+    data.extend_from_slice(&(file_len as u32).to_le_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&(off_bits as u32).to_le_bytes());
+    data.extend_from_slice(dib);
+    decode_bmp_with_config(&data, config)
+}
+
+/// Offset of the pixel array in a packed DIB, past the header, bitfield
+/// masks, and palette
+pub(crate) fn dib_pixel_offset(dib: &[u8]) -> Result<usize, ImageHardenError> {
+    if dib.len() < 40 {
+        return Err(bmp_error("Truncated DIB info header"));
+    }
+    let le32 = |at: usize| u32::from_le_bytes([dib[at], dib[at + 1], dib[at + 2], dib[at + 3]]);
+    let header_size = le32(0) as usize;
+    if !INFO_HEADER_SIZES.contains(&header_size) {
+        return Err(bmp_error(format!(
+            "Unsupported BMP header size {}",
+            header_size
+        )));
+    }
+    let bit_count = u16::from_le_bytes([dib[14], dib[15]]);
+    let compression = le32(16);
+    let colors_used = le32(32);
+
+    let mut offset = header_size;
+    if compression == BI_BITFIELDS && header_size == 40 {
+        offset += 12;
+    }
+    if bit_count <= 8 {
+        let entries = if colors_used == 0 {
+            1u32 << bit_count
+        } else {
+            colors_used
+        };
+        if entries > MAX_PALETTE_ENTRIES {
+            return Err(bmp_error(format!(
+                "Palette of {} entries exceeds {}",
+                entries, MAX_PALETTE_ENTRIES
+            )));
+        }
+        offset += entries as usize * 4;
+    }
+    Ok(offset)
+}
+
+/// Expand RLE8 data to one palette index per pixel, bottom-up row order
+///
+/// Skipped pixels (delta codes, early end of line) keep index 0. Runs that
+/// would write past a row or the last row are rejected.
+fn decode_rle8(rle: &[u8], width: u32, height: u32) -> Result<Vec<u8>, ImageHardenError> {
+    let (width, height) = (width as usize, height as usize);
+    let mut out = vec![0u8; width * height];
+    let (mut x, mut y) = (0usize, 0usize);
+    let mut pos = 0usize;
+    let overrun = || bmp_error("RLE8 run exceeds image bounds");
+
+    while pos + 1 < rle.len() {
+        let (count, value) = (rle[pos] as usize, rle[pos + 1]);
+        pos += 2;
+        match (count, value) {
+            (0, 0) => {
+                x = 0;
+                y += 1;
+            }
+            (0, 1) => return Ok(out),
+            (0, 2) => {
+                let delta = rle.get(pos..pos + 2).ok_or_else(overrun)?;
+                x += delta[0] as usize;
+                y += delta[1] as usize;
+                pos += 2;
+            }
+            (0, literal) => {
+                let literal = literal as usize;
+                let bytes = rle.get(pos..pos + literal).ok_or_else(overrun)?;
+                if y >= height || x + literal > width {
+                    return Err(overrun());
+                }
+                out[y * width + x..y * width + x + literal].copy_from_slice(bytes);
+                x += literal;
+                // Literal runs are padded to a 16-bit boundary
+                pos += literal + (literal & 1);
+            }
+            (run, index) => {
+                if y >= height || x + run > width {
+                    return Err(overrun());
+                }
+                out[y * width + x..y * width + x + run].fill(index);
+                x += run;
+            }
+        }
+        if y > height {
+            return Err(overrun());
+        }
+    }
+    // Missing end-of-bitmap marker: keep what was decoded
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BITMAPFILEHEADER + BITMAPINFOHEADER, then `tables` and `pixels`
+    fn bmp(
+        width: i32,
+        height: i32,
+        bits: u16,
+        compression: u32,
+        tables: &[u8],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let off_bits = (14 + 40 + tables.len()) as u32;
+        let mut out = b"BM".to_vec();
+        out.extend_from_slice(&(off_bits + pixels.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&off_bits.to_le_bytes());
+        out.extend_from_slice(&40u32.to_le_bytes());
+        out.extend_from_slice(&width.to_le_bytes());
+        out.extend_from_slice(&height.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&bits.to_le_bytes());
+        out.extend_from_slice(&compression.to_le_bytes());
+        out.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+        out.extend_from_slice(&[0; 8]); // resolution
+        let colors = if bits == 8 {
+            tables.len() as u32 / 4
+        } else {
+            0
+        };
+        out.extend_from_slice(&colors.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(tables);
+        out.extend_from_slice(pixels);
+        out
+    }
+
+    #[test]
+    fn test_24_bit_bottom_up() {
+        // Rows are padded to 4 bytes and stored bottom row first
+        let pixels = [
+            0, 0, 255, 0, 255, 0, 0, 0, // bottom: red, green
+            255, 0, 0, 255, 255, 255, 0, 0, // top: blue, white
+        ];
+        let image = decode_bmp(&bmp(2, 2, 24, BI_RGB, &[], &pixels)).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 2, 4));
+        assert_eq!(
+            image.data,
+            [0, 0, 255, 255, 255, 255, 255, 255, 255, 0, 0, 255, 0, 255, 0, 255]
+        );
+
+        // Top-down storage flips the result
+        let flipped = decode_bmp(&bmp(2, -2, 24, BI_RGB, &[], &pixels)).unwrap();
+        assert_eq!(&flipped.data[..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_8_bit_palette() {
+        let palette = [0, 0, 0, 0, 255, 255, 255, 0];
+        let image = decode_bmp(&bmp(3, 1, 8, BI_RGB, &palette, &[1, 0, 1, 0])).unwrap();
+        assert_eq!(
+            image.data,
+            [255, 255, 255, 255, 0, 0, 0, 255, 255, 255, 255, 255]
+        );
+
+        // Index beyond the two-entry palette
+        assert!(decode_bmp(&bmp(3, 1, 8, BI_RGB, &palette, &[2, 0, 1, 0])).is_err());
+    }
+
+    #[test]
+    fn test_bad_offsets_rejected() {
+        let pixels = [0u8; 8];
+        let good = bmp(2, 1, 24, BI_RGB, &[], &pixels);
+        assert!(decode_bmp(&good).is_ok());
+
+        // bfOffBits past the end of the file
+        let mut past_end = good.clone();
+        past_end[10..14].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+        assert!(matches!(
+            decode_bmp(&past_end),
+            Err(ImageHardenError::BmpError(_))
+        ));
+
+        // bfOffBits pointing back into the headers
+        let mut into_header = good.clone();
+        into_header[10..14].copy_from_slice(&20u32.to_le_bytes());
+        assert!(decode_bmp(&into_header).is_err());
+
+        // biSizeImage smaller than the raster
+        let mut undersized = good.clone();
+        undersized[34..38].copy_from_slice(&4u32.to_le_bytes());
+        assert!(decode_bmp(&undersized).is_err());
+
+        // Raster cut short
+        assert!(decode_bmp(&good[..good.len() - 1]).is_err());
+
+        // Width whose stride would overflow is caught by the dimension cap
+        let mut huge = good;
+        huge[18..22].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(decode_bmp(&huge).is_err());
+    }
+
+    #[test]
+    fn test_rle8_strict_mode() {
+        let palette = [0, 0, 0, 0, 0, 0, 255, 0];
+        // Row 0: three of index 1, end of line; row 1: literal [1, 0, 1], end of bitmap
+        let rle = [3, 1, 0, 0, 0, 3, 1, 0, 1, 0, 0, 1];
+        let data = bmp(3, 2, 8, BI_RLE8, &palette, &rle);
+        assert!(decode_bmp(&data).is_err());
+
+        let relaxed = BmpDecoderConfig {
+            strict_mode: false,
+            ..BmpDecoderConfig::default()
+        };
+        let image = decode_bmp_with_config(&data, &relaxed).unwrap();
+        // Bottom-up: the literal row is on top
+        assert_eq!(
+            &image.data[..12],
+            &[255, 0, 0, 255, 0, 0, 0, 255, 255, 0, 0, 255]
+        );
+        assert_eq!(
+            &image.data[12..],
+            &[255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255]
+        );
+
+        // A run past the row end
+        let overrun = bmp(3, 2, 8, BI_RLE8, &palette, &[4, 1, 0, 1]);
+        assert!(decode_bmp_with_config(&overrun, &relaxed).is_err());
+    }
+}
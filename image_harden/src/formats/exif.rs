@@ -3,6 +3,7 @@
 ///! Security measures:
 ///! - Strict metadata size limits (max 1 MB)
 ///! - Tag count validation
+///! - Budget on value bytes referenced by out-of-line entries (EXIF bombs)
 ///! - Strip metadata by default in hardened mode
 ///! - UTF-8 validation for text fields
 ///! - GPS data stripping option (privacy)
@@ -16,6 +17,18 @@ const MAX_EXIF_SIZE: usize = 1024 * 1024;
 /// Maximum number of EXIF tags
 const MAX_TAG_COUNT: u32 = 512;
 
+/// Maximum IFDs walked (IFD0 chain plus Exif, GPS, and Interop sub-IFDs)
+const MAX_IFD_COUNT: usize = 16;
+
+/// Maximum value bytes referenced across all out-of-line entries
+/// Non-overlapping values always fit in the blob, so this matches the size cap.
+const MAX_VALUE_BYTES: usize = MAX_EXIF_SIZE;
+
+/// Sub-IFD pointer tags
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_INTEROP_IFD: u16 = 0xA005;
+
 /// EXIF magic bytes (in JPEG APP1 segment)
 const EXIF_MAGIC: &[u8] = b"Exif\x00\x00";
 
@@ -30,6 +43,7 @@ const TIFF_MAGIC_BE: &[u8] = b"MM\x00\x2A";
 pub struct ExifConfig {
     pub max_exif_size: usize,
     pub max_tag_count: u32,
+    pub max_value_bytes: usize,
     pub strip_exif: bool,
     pub strip_gps: bool,
     pub validate_utf8: bool,
@@ -41,6 +55,7 @@ impl Default for ExifConfig {
         Self {
             max_exif_size: MAX_EXIF_SIZE,
             max_tag_count: MAX_TAG_COUNT,
+            max_value_bytes: MAX_VALUE_BYTES,
             strip_exif: true, // Default: strip EXIF in hardened mode
             strip_gps: true,  // Default: strip GPS for privacy
             validate_utf8: true,
@@ -144,20 +159,132 @@ pub fn validate_exif_with_config(
         ));
     }
 
-    // Estimate tag count (simplified - would need full IFD parsing for accuracy)
-    // For now, just return a safe estimate
-    let tag_count = 0u32; // TODO: Implement proper IFD parsing
-
-    // Check for GPS IFD (would require parsing IFD entries)
-    let has_gps = false; // TODO: Implement GPS detection
+    let walk = walk_ifds(tiff_header, byte_order, ifd0_offset as usize, config)?;
 
     Ok(ExifInfo {
         byte_order,
-        tag_count,
-        has_gps,
+        tag_count: walk.tag_count,
+        has_gps: walk.has_gps,
     })
 }
 
+/// Totals gathered while walking the IFDs
+struct IfdWalk {
+    tag_count: u32,
+    has_gps: bool,
+}
+
+/// Size in bytes of one value of a TIFF field type, None if unknown
+fn type_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1),   // BYTE, ASCII, SBYTE, UNDEFINED
+        3 | 8 => Some(2),           // SHORT, SSHORT
+        4 | 9 | 11 | 13 => Some(4), // LONG, SLONG, FLOAT, IFD
+        5 | 10 | 12 => Some(8),     // RATIONAL, SRATIONAL, DOUBLE
+        _ => None,
+    }
+}
+
+/// Walk the IFD0 chain and the Exif, GPS, and Interop sub-IFDs
+///
+/// Every entry whose value does not fit inline must point inside the blob,
+/// and the bytes those entries reference are summed against
+/// `max_value_bytes`, since many entries aimed at one large region would
+/// otherwise multiply the work for any consumer reading the values.
+fn walk_ifds(
+    tiff: &[u8],
+    byte_order: ByteOrder,
+    ifd0: usize,
+    config: &ExifConfig,
+) -> Result<IfdWalk, ImageHardenError> {
+    let read = |pos: usize, len: usize| {
+        pos.checked_add(len)
+            .and_then(|end| tiff.get(pos..end))
+            .ok_or_else(|| ImageHardenError::ExifError(format!("Truncated IFD at offset {}", pos)))
+    };
+    let u16_at = |pos: usize| -> Result<u16, ImageHardenError> {
+        let bytes: [u8; 2] = read(pos, 2)?.try_into().unwrap();
+        Ok(match byte_order {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        })
+    };
+    let u32_at = |pos: usize| -> Result<u32, ImageHardenError> {
+        let bytes: [u8; 4] = read(pos, 4)?.try_into().unwrap();
+        Ok(match byte_order {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        })
+    };
+
+    let mut walk = IfdWalk {
+        tag_count: 0,
+        has_gps: false,
+    };
+    let mut value_bytes = 0usize;
+    let mut pending = vec![ifd0];
+    let mut visited = Vec::new();
+    while let Some(ifd) = pending.pop() {
+        // Offset 0 ends a chain; an IFD seen before is a loop
+        if ifd == 0 || visited.contains(&ifd) {
+            continue;
+        }
+        if visited.len() == MAX_IFD_COUNT {
+            return Err(ImageHardenError::ExifError(format!(
+                "More than {} IFDs",
+                MAX_IFD_COUNT
+            )));
+        }
+        visited.push(ifd);
+
+        let entries = u16_at(ifd)? as usize;
+        walk.tag_count = walk.tag_count.saturating_add(entries as u32);
+        if walk.tag_count > config.max_tag_count {
+            return Err(ImageHardenError::ExifError(format!(
+                "EXIF tag count exceeds maximum {}",
+                config.max_tag_count
+            )));
+        }
+
+        for index in 0..entries {
+            let entry = ifd + 2 + index * 12;
+            let tag = u16_at(entry)?;
+            let kind = u16_at(entry + 2)?;
+            let Some(size) = type_size(kind) else {
+                if config.strict_mode {
+                    return Err(ImageHardenError::ExifError(format!(
+                        "Tag {:#06x} has unknown type {}",
+                        tag, kind
+                    )));
+                }
+                continue;
+            };
+            let len = (u32_at(entry + 4)? as usize).saturating_mul(size);
+            if len > 4 {
+                let start = u32_at(entry + 8)? as usize;
+                read(start, len)?;
+                value_bytes = value_bytes.saturating_add(len);
+                if value_bytes > config.max_value_bytes {
+                    return Err(ImageHardenError::ExifError(format!(
+                        "EXIF entries reference more than {} value bytes",
+                        config.max_value_bytes
+                    )));
+                }
+            }
+            match tag {
+                TAG_GPS_IFD => {
+                    walk.has_gps = true;
+                    pending.push(u32_at(entry + 8)? as usize);
+                }
+                TAG_EXIF_IFD | TAG_INTEROP_IFD => pending.push(u32_at(entry + 8)? as usize),
+                _ => {}
+            }
+        }
+        pending.push(u32_at(ifd + 2 + entries * 12)? as usize);
+    }
+    Ok(walk)
+}
+
 /// Strip EXIF data from image (default hardened mode behavior)
 pub fn strip_exif(_image_data: &[u8]) -> Result<Vec<u8>, ImageHardenError> {
     // TODO: Implement EXIF stripping for various formats
@@ -215,10 +342,68 @@ mod tests {
         let mut data = Vec::from(EXIF_MAGIC);
         data.extend_from_slice(TIFF_MAGIC_LE);
         data.extend_from_slice(&[0x08, 0x00, 0x00, 0x00]); // IFD offset
+        data.extend_from_slice(&[0; 6]); // Empty IFD0, no next IFD
         let result = validate_exif(&data);
         assert!(result.is_ok());
         if let Ok(info) = result {
             matches!(info.byte_order, ByteOrder::LittleEndian);
         }
     }
+
+    /// Little-endian EXIF with IFD0 holding (tag, type, count, value)
+    /// entries, followed by `values` (starting at offset 14 + 12 * entries)
+    fn exif(entries: &[(u16, u16, u32, u32)], values: &[u8]) -> Vec<u8> {
+        let mut data = Vec::from(TIFF_MAGIC_LE);
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for &(tag, kind, count, value) in entries {
+            data.extend_from_slice(&tag.to_le_bytes());
+            data.extend_from_slice(&kind.to_le_bytes());
+            data.extend_from_slice(&count.to_le_bytes());
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(values);
+        data
+    }
+
+    #[test]
+    fn test_value_bytes_budget() {
+        // 300 UNDEFINED entries all pointing at the same 4000-byte region:
+        // a 12 KB blob referencing 1.2 MB of values
+        let values_at = 14 + 12 * 300;
+        let entries: Vec<_> = (0..300)
+            .map(|i| (0x9000 + i as u16, 7, 4000, values_at))
+            .collect();
+        let bomb = exif(&entries, &[0; 4000]);
+        assert!(matches!(
+            validate_exif(&bomb),
+            Err(ImageHardenError::ExifError(_))
+        ));
+        let single = exif(&[(0x9000, 7, 4000, 26)], &[0; 4000]);
+        assert_eq!(validate_exif(&single).unwrap().tag_count, 1);
+
+        // The budget is configurable; inline values (4 bytes or less) are free
+        let config = ExifConfig {
+            max_value_bytes: 100,
+            ..ExifConfig::default()
+        };
+        let one = exif(&[(0x9000, 7, 60, 26)], &[0; 60]);
+        assert!(validate_exif_with_config(&one, &config).is_ok());
+        let two = exif(&[(0x9000, 7, 60, 38), (0x9001, 7, 60, 38)], &[0; 60]);
+        assert!(validate_exif_with_config(&two, &config).is_err());
+        let inline: Vec<_> = (0..200).map(|i| (0x9000 + i as u16, 7, 4, 0)).collect();
+        assert!(validate_exif_with_config(&exif(&inline, &[]), &config).is_ok());
+
+        // A value region past the end of the blob
+        assert!(validate_exif(&exif(&[(0x9000, 7, 64, 26)], &[0; 63])).is_err());
+    }
+
+    #[test]
+    fn test_gps_ifd_detected() {
+        // GPS pointer to an empty IFD right after IFD0
+        let info = validate_exif(&exif(&[(TAG_GPS_IFD, 4, 1, 26)], &[0; 6])).unwrap();
+        assert!(info.has_gps);
+        assert_eq!(info.tag_count, 1);
+    }
 }
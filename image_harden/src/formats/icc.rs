@@ -6,9 +6,12 @@
 ///! - Version validation
 ///! - Magic byte validation ('acsp')
 ///! - Strip profiles by default in hardened mode
+///! - Profiles validated before lcms2 parses them for sRGB conversion
 ///! - Fail-closed error handling
 
 use crate::ImageHardenError;
+use std::ffi::c_void;
+use std::ptr;
 
 /// Maximum allowed ICC profile size (2 MB)
 const MAX_PROFILE_SIZE: usize = 2 * 1024 * 1024;
@@ -22,6 +25,19 @@ const ICC_MAGIC: &[u8] = b"acsp";
 /// ICC profile magic offset in header
 const ICC_MAGIC_OFFSET: usize = 36;
 
+/// lcms2 pixel format for packed 8-bit RGB (`TYPE_RGB_8`)
+const TYPE_RGB_8: u32 = (4 << 16) | (3 << 3) | 1;
+
+/// lcms2 relative colorimetric rendering intent
+const INTENT_RELATIVE_COLORIMETRIC: u32 = 1;
+
+/// Display P3 (SMPTE EG 432-1) primaries and D65 white point as CIE xy
+const DISPLAY_P3_PRIMARIES: [(f64, f64); 3] = [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)];
+const D65_WHITE_POINT: (f64, f64) = (0.3127, 0.3290);
+
+/// sRGB transfer curve as lcms2 parametric type 4 (also used by Display P3)
+const SRGB_CURVE_PARAMS: [f64; 5] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+
 /// Hardened ICC profile configuration
 #[derive(Debug, Clone)]
 pub struct IccProfileConfig {
@@ -147,6 +163,131 @@ pub fn validate_icc_profile_with_config(
     })
 }
 
+/// Source color space for conversion to sRGB
+#[derive(Debug, Clone, Copy)]
+pub enum SourceProfile<'a> {
+    /// Embedded ICC profile; validated before lcms2 parses it
+    Icc(&'a [u8]),
+    /// Display P3 signalled without an ICC profile (e.g. HEIF NCLX primaries 12)
+    DisplayP3,
+}
+
+/// lcms2 transform from a source profile to sRGB over packed 8-bit RGB
+pub struct SrgbTransform {
+    transform: crate::cmsHTRANSFORM,
+}
+
+impl SrgbTransform {
+    /// Build a transform from `source` to sRGB
+    pub fn new(source: SourceProfile<'_>) -> Result<Self, ImageHardenError> {
+        let input = match source {
+            SourceProfile::Icc(data) => {
+                validate_icc_profile_with_config(data, &IccProfileConfig::default())?;
+                unsafe {
+                    crate::cmsOpenProfileFromMem(data.as_ptr() as *const c_void, data.len() as u32)
+                }
+            }
+            SourceProfile::DisplayP3 => display_p3_profile(),
+        };
+        if input.is_null() {
+            return Err(ImageHardenError::IccError(
+                "Failed to open source color profile".to_string(),
+            ));
+        }
+
+        let output = unsafe { crate::cmsCreate_sRGBProfile() };
+        if output.is_null() {
+            unsafe { crate::cmsCloseProfile(input) };
+            return Err(ImageHardenError::IccError(
+                "Failed to create sRGB profile".to_string(),
+            ));
+        }
+
+        let transform = unsafe {
+            crate::cmsCreateTransform(
+                input,
+                TYPE_RGB_8,
+                output,
+                TYPE_RGB_8,
+                INTENT_RELATIVE_COLORIMETRIC,
+                0,
+            )
+        };
+        // The transform keeps what it needs; the profiles can go now
+        unsafe {
+            crate::cmsCloseProfile(input);
+            crate::cmsCloseProfile(output);
+        }
+        if transform.is_null() {
+            return Err(ImageHardenError::IccError(
+                "Failed to create color transform (profile is not RGB?)".to_string(),
+            ));
+        }
+
+        Ok(Self { transform })
+    }
+
+    /// Convert packed RGB pixels in place
+    pub fn apply(&self, pixels: &mut [u8]) -> Result<(), ImageHardenError> {
+        if pixels.len() % 3 != 0 {
+            return Err(ImageHardenError::IccError(format!(
+                "Pixel buffer length {} is not a multiple of 3",
+                pixels.len()
+            )));
+        }
+        let count = u32::try_from(pixels.len() / 3).map_err(|_| {
+            ImageHardenError::IccError("Pixel buffer too large for one transform".to_string())
+        })?;
+        // lcms2 supports in-place transforms when input and output formats match
+        unsafe {
+            crate::cmsDoTransform(
+                self.transform,
+                pixels.as_ptr() as *const c_void,
+                pixels.as_mut_ptr() as *mut c_void,
+                count,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SrgbTransform {
+    fn drop(&mut self) {
+        unsafe { crate::cmsDeleteTransform(self.transform) };
+    }
+}
+
+/// Convert packed 8-bit RGB pixels from `source` to sRGB in place
+pub fn convert_rgb8_to_srgb(
+    source: SourceProfile<'_>,
+    pixels: &mut [u8],
+) -> Result<(), ImageHardenError> {
+    SrgbTransform::new(source)?.apply(pixels)
+}
+
+/// Build an in-memory Display P3 profile (P3 primaries, D65, sRGB curve)
+fn display_p3_profile() -> crate::cmsHPROFILE {
+    let xyy = |(x, y): (f64, f64)| crate::cmsCIExyY { x, y, Y: 1.0 };
+    let white = xyy(D65_WHITE_POINT);
+    let primaries = crate::cmsCIExyYTRIPLE {
+        Red: xyy(DISPLAY_P3_PRIMARIES[0]),
+        Green: xyy(DISPLAY_P3_PRIMARIES[1]),
+        Blue: xyy(DISPLAY_P3_PRIMARIES[2]),
+    };
+
+    unsafe {
+        let curve =
+            crate::cmsBuildParametricToneCurve(ptr::null_mut(), 4, SRGB_CURVE_PARAMS.as_ptr());
+        if curve.is_null() {
+            return ptr::null_mut();
+        }
+        let curves = [curve, curve, curve];
+        let profile = crate::cmsCreateRGBProfile(&white, &primaries, curves.as_ptr());
+        crate::cmsFreeToneCurve(curve);
+        profile
+    }
+}
+
 /// Strip ICC profile from image data (default hardened mode behavior)
 pub fn strip_icc_profile(_image_data: &[u8]) -> Result<Vec<u8>, ImageHardenError> {
     // TODO: Implement ICC profile stripping for various formats
@@ -188,4 +329,22 @@ mod tests {
         let result = validate_icc_profile(&data);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_display_p3_to_srgb() {
+        // Neutral gray is the same in both spaces; saturated P3 colors move
+        let mut pixels = vec![128, 128, 128, 200, 100, 50, 100, 150, 200];
+        convert_rgb8_to_srgb(SourceProfile::DisplayP3, &mut pixels).unwrap();
+
+        let expected = [128, 128, 128, 215, 93, 31, 83, 152, 205];
+        for (got, want) in pixels.iter().zip(expected.iter()) {
+            assert!((*got as i32 - *want as i32).abs() <= 3, "{:?}", pixels);
+        }
+    }
+
+    #[test]
+    fn test_invalid_icc_source_rejected() {
+        let mut pixels = vec![0u8; 3];
+        assert!(convert_rgb8_to_srgb(SourceProfile::Icc(&[0u8; 132]), &mut pixels).is_err());
+    }
 }
@@ -0,0 +1,337 @@
+///! ICO / favicon decoder with comprehensive hardening
+///!
+///! An ICO file is a directory of independently encoded images, each either
+///! a PNG stream or a headerless BMP (packed DIB plus a 1-bit AND mask). The
+///! largest entry within limits is selected and its payload is handed to the
+///! hardened PNG or BMP decoder.
+///!
+///! Security measures:
+///! - Entry count capped; every entry's offset and size validated against
+///!   the file before any payload is touched
+///! - Payload dimensions re-checked from the embedded header, not trusted
+///!   from the directory
+///! - PNG payloads decoded by the hardened libpng path, BMP payloads by the
+///!   pure-Rust BMP decoder in strict mode
+///! - AND mask bounds-checked before it is applied
+///! - File size caps (max 16 MB)
+///! - Fail-closed error handling
+use crate::formats::bmp::{decode_dib_with_config, dib_pixel_offset, BmpDecoderConfig};
+use crate::{decode_png_full, read_image_dimensions, DecodedImage, ImageHardenError};
+
+/// Maximum allowed icon dimensions (the ICO directory cannot express more)
+const MAX_DIMENSION: u32 = 256;
+
+/// Maximum allowed file size (16 MB)
+const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Maximum directory entries
+const MAX_ENTRIES: usize = 256;
+
+/// ICONDIR and ICONDIRENTRY sizes
+const DIR_HEADER_LEN: usize = 6;
+const DIR_ENTRY_LEN: usize = 16;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Hardened ICO decoder configuration
+#[derive(Debug, Clone)]
+pub struct IcoDecoderConfig {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_file_size: usize,
+    pub max_entries: usize,
+}
+
+impl Default for IcoDecoderConfig {
+    fn default() -> Self {
+        Self {
+            max_width: MAX_DIMENSION,
+            max_height: MAX_DIMENSION,
+            max_file_size: MAX_FILE_SIZE,
+            max_entries: MAX_ENTRIES,
+        }
+    }
+}
+
+/// One ICONDIRENTRY, already validated against the file
+struct IconEntry {
+    width: u32,
+    height: u32,
+    bit_count: u16,
+    offset: usize,
+    size: usize,
+}
+
+/// True for an icon (type 1) ICONDIR with at least one entry
+pub fn is_ico(data: &[u8]) -> bool {
+    data.len() >= DIR_HEADER_LEN + DIR_ENTRY_LEN
+        && data.starts_with(&[0, 0, 1, 0])
+        && u16::from_le_bytes([data[4], data[5]]) > 0
+        // Reserved byte of the first entry
+        && data[9] == 0
+}
+
+/// Decode the largest image of an ICO file with hardening
+pub fn decode_ico(data: &[u8]) -> Result<DecodedImage, ImageHardenError> {
+    decode_ico_with_config(data, &IcoDecoderConfig::default())
+}
+
+fn ico_error(msg: impl Into<String>) -> ImageHardenError {
+    ImageHardenError::IcoError(msg.into())
+}
+
+/// Decode ICO with custom configuration
+pub fn decode_ico_with_config(
+    data: &[u8],
+    config: &IcoDecoderConfig,
+) -> Result<DecodedImage, ImageHardenError> {
+    if data.len() > config.max_file_size {
+        return Err(ico_error(format!(
+            "File size {} exceeds maximum {}",
+            data.len(),
+            config.max_file_size
+        )));
+    }
+    if data.len() < DIR_HEADER_LEN || !data.starts_with(&[0, 0, 1, 0]) {
+        return Err(ico_error("Invalid ICO header"));
+    }
+
+    let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+    if count == 0 || count > config.max_entries {
+        return Err(ico_error(format!(
+            "Entry count {} outside 1..={}",
+            count, config.max_entries
+        )));
+    }
+    let dir_end = DIR_HEADER_LEN + count * DIR_ENTRY_LEN;
+    if dir_end > data.len() {
+        return Err(ico_error("Truncated ICO directory"));
+    }
+
+    let mut best: Option<IconEntry> = None;
+    for raw in data[DIR_HEADER_LEN..dir_end].chunks_exact(DIR_ENTRY_LEN) {
+        let le32 = |at: usize| u32::from_le_bytes([raw[at], raw[at + 1], raw[at + 2], raw[at + 3]]);
+        // A zero byte means 256
+        let entry = IconEntry {
+            width: if raw[0] == 0 { 256 } else { raw[0] as u32 },
+            height: if raw[1] == 0 { 256 } else { raw[1] as u32 },
+            bit_count: u16::from_le_bytes([raw[6], raw[7]]),
+            size: le32(8) as usize,
+            offset: le32(12) as usize,
+        };
+
+        // Payloads must sit after the directory and inside the file
+        let in_bounds = entry.size > 0
+            && entry.offset >= dir_end
+            && entry
+                .offset
+                .checked_add(entry.size)
+                .is_some_and(|end| end <= data.len());
+        if !in_bounds {
+            return Err(ico_error(format!(
+                "Entry of {} bytes at offset {} outside {}..={}",
+                entry.size,
+                entry.offset,
+                dir_end,
+                data.len()
+            )));
+        }
+
+        if entry.width > config.max_width || entry.height > config.max_height {
+            continue;
+        }
+        let rank = |e: &IconEntry| (e.width as u64 * e.height as u64, e.bit_count);
+        if best
+            .as_ref()
+            .is_none_or(|current| rank(&entry) > rank(current))
+        {
+            best = Some(entry);
+        }
+    }
+
+    let entry = best.ok_or_else(|| ico_error("No ICO entry within dimension limits"))?;
+    let payload = &data[entry.offset..entry.offset + entry.size];
+
+    if payload.starts_with(PNG_SIGNATURE) {
+        // The directory may understate what the PNG actually holds
+        let (width, height) = read_image_dimensions(payload)
+            .ok_or_else(|| ico_error("Truncated PNG entry header"))?;
+        if width > config.max_width || height > config.max_height {
+            return Err(ico_error(format!(
+                "PNG entry {}x{} exceeds maximum {}x{}",
+                width, height, config.max_width, config.max_height
+            )));
+        }
+        decode_png_full(payload)
+    } else {
+        decode_icon_bitmap(payload, config)
+    }
+}
+
+/// Decode a BMP icon entry: a packed DIB whose height covers the color
+/// bitmap and the AND mask stacked below it
+fn decode_icon_bitmap(
+    payload: &[u8],
+    config: &IcoDecoderConfig,
+) -> Result<DecodedImage, ImageHardenError> {
+    if payload.len() < 40 {
+        return Err(ico_error("Truncated BMP entry header"));
+    }
+    let le32 = |at: usize| {
+        u32::from_le_bytes([
+            payload[at],
+            payload[at + 1],
+            payload[at + 2],
+            payload[at + 3],
+        ]) as i32
+    };
+    let (raw_width, raw_height) = (le32(4), le32(8));
+    // Icon bitmaps are always bottom-up with an even doubled height
+    if raw_width <= 0 || raw_height <= 0 || raw_height % 2 != 0 {
+        return Err(ico_error(format!(
+            "Invalid BMP entry geometry {}x{}",
+            raw_width, raw_height
+        )));
+    }
+    let (width, height) = (raw_width as u32, raw_height as u32 / 2);
+    let bit_count = u16::from_le_bytes([payload[14], payload[15]]);
+
+    let mut dib = payload.to_vec();
+    dib[8..12].copy_from_slice(&height.to_le_bytes());
+    let bmp_config = BmpDecoderConfig {
+        max_width: config.max_width,
+        max_height: config.max_height,
+        max_file_size: config.max_file_size,
+        strict_mode: true,
+        reserved_alpha: bit_count == 32,
+    };
+    let mut image = decode_dib_with_config(&dib, &bmp_config)?;
+
+    // 32-bit entries carry real alpha; an all-zero alpha channel means the
+    // AND mask is authoritative, as it always is for lower bit depths
+    if bit_count == 32 && image.data.chunks_exact(4).any(|px| px[3] != 0) {
+        return Ok(image);
+    }
+
+    // Dimensions are capped by the BMP decoder, so strides cannot overflow
+    let (w, h) = (width as usize, height as usize);
+    let color_stride = (w * bit_count as usize).div_ceil(32) * 4;
+    let mask_stride = w.div_ceil(32) * 4;
+    let mask_start = dib_pixel_offset(payload)? + color_stride * h;
+    let mask = payload
+        .get(mask_start..mask_start + mask_stride * h)
+        .ok_or_else(|| ico_error("Truncated ICO AND mask"))?;
+
+    image.had_alpha = false;
+    for (row_index, row) in mask.chunks_exact(mask_stride).enumerate() {
+        let y = h - 1 - row_index;
+        for x in 0..w {
+            let transparent = row[x / 8] & (0x80 >> (x % 8)) != 0;
+            image.data[(y * w + x) * 4 + 3] = if transparent { 0 } else { 255 };
+            image.had_alpha |= transparent;
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    // ICONDIR followed by one entry per (width, height, bits, payload)
+    fn ico(entries: &[(u8, u8, u16, &[u8])]) -> Vec<u8> {
+        let mut out = vec![0, 0, 1, 0];
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        let mut offset = DIR_HEADER_LEN + entries.len() * DIR_ENTRY_LEN;
+        for (width, height, bits, payload) in entries {
+            out.extend_from_slice(&[*width, *height, 0, 0]);
+            out.extend_from_slice(&1u16.to_le_bytes());
+            out.extend_from_slice(&bits.to_le_bytes());
+            out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += payload.len();
+        }
+        for (_, _, _, payload) in entries {
+            out.extend_from_slice(payload);
+        }
+        out
+    }
+
+    // BITMAPINFOHEADER with the doubled icon height, then color rows and mask
+    fn icon_dib(width: i32, height: i32, bits: u16, rows: &[u8]) -> Vec<u8> {
+        let mut out = 40u32.to_le_bytes().to_vec();
+        out.extend_from_slice(&width.to_le_bytes());
+        out.extend_from_slice(&(height * 2).to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&bits.to_le_bytes());
+        out.extend_from_slice(&[0; 24]);
+        out.extend_from_slice(rows);
+        out
+    }
+
+    #[test]
+    fn test_largest_png_entry_selected() {
+        // 2x2 24-bit color rows, then 2 rows of 4-byte mask
+        let small = icon_dib(2, 2, 24, &[0; 16 + 8]);
+        let large = test_support::png_rgba(32, 32, [10, 20, 30, 255]);
+        let data = ico(&[(2, 2, 24, &small), (32, 32, 32, &large), (1, 1, 24, &small)]);
+        assert!(is_ico(&data));
+
+        let image = decode_ico(&data).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (32, 32, 4));
+        assert_eq!(&image.data[..4], &[10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_bmp_entry_and_mask() {
+        let rows = [
+            0, 0, 255, 0, 255, 0, 0, 0, // bottom: red, green
+            255, 0, 0, 255, 255, 255, 0, 0, // top: blue, white
+            0x40, 0, 0, 0, // bottom mask: green transparent
+            0, 0, 0, 0, // top mask: opaque
+        ];
+        let image = decode_ico(&ico(&[(2, 2, 24, &icon_dib(2, 2, 24, &rows))])).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert!(image.had_alpha);
+        assert_eq!(
+            image.data,
+            [0, 0, 255, 255, 255, 255, 255, 255, 255, 0, 0, 255, 0, 255, 0, 0]
+        );
+
+        // Color rows without the mask
+        let truncated = icon_dib(2, 2, 24, &rows[..16]);
+        assert!(matches!(
+            decode_ico(&ico(&[(2, 2, 24, &truncated)])),
+            Err(ImageHardenError::IcoError(_))
+        ));
+    }
+
+    #[test]
+    fn test_entry_bounds_rejected() {
+        let payload = icon_dib(1, 1, 24, &[0; 8]);
+        let good = ico(&[(1, 1, 24, &payload)]);
+        assert!(decode_ico(&good).is_ok());
+
+        // Offset past the end of the file
+        let mut past_end = good.clone();
+        past_end[18..22].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+        assert!(decode_ico(&past_end).is_err());
+
+        // Size running past the end of the file
+        let mut oversized = good.clone();
+        oversized[14..18].copy_from_slice(&(payload.len() as u32 + 1).to_le_bytes());
+        assert!(decode_ico(&oversized).is_err());
+
+        // Offset pointing back into the directory
+        let mut into_dir = good.clone();
+        into_dir[18..22].copy_from_slice(&6u32.to_le_bytes());
+        assert!(decode_ico(&into_dir).is_err());
+
+        // Directory claiming more entries than the file holds
+        let mut miscounted = good;
+        miscounted[4..6].copy_from_slice(&200u16.to_le_bytes());
+        assert!(decode_ico(&miscounted).is_err());
+        assert!(decode_ico(&[0, 0, 1, 0, 0, 0]).is_err());
+    }
+}
@@ -5,6 +5,9 @@
 //! - JPEG XL (next-gen lossy/lossless)
 //! - TIFF (Tagged Image File Format)
 //! - OpenEXR (HDR image format)
+//! - PNM (Netpbm PBM/PGM/PPM)
+//! - BMP (Windows bitmap)
+//! - ICO (Windows icon / favicon)
 //! - ICC color profiles
 //! - EXIF metadata
 
@@ -26,6 +29,11 @@ pub mod tiff;
 #[cfg(feature = "openexr")]
 pub mod exr;
 
+// Pure-Rust decoders, always built
+pub mod bmp;
+pub mod ico;
+pub mod pnm;
+
 // Hidden-path components
 #[cfg(feature = "icc")]
 pub mod icc;
@@ -0,0 +1,389 @@
+///! Netpbm (PBM/PGM/PPM) decoder with comprehensive hardening
+///!
+///! Security measures:
+///! - Pure Rust, no native library involved
+///! - Strict dimension limits (max 16384x16384) and the shared pixel budget
+///! - File size caps (max 256 MB - ASCII rasters are verbose)
+///! - Bounded header tokens; maxval restricted to 1..=65535
+///! - Samples above maxval and short rasters are rejected, not clamped
+///! - Fail-closed error handling
+use crate::{check_nonzero_dimensions, check_pixel_budget, DecodedImage, ImageHardenError};
+
+/// Maximum allowed PNM image dimensions
+const MAX_DIMENSION: u32 = 16384;
+
+/// Maximum allowed file size (256 MB)
+const MAX_FILE_SIZE: usize = 256 * 1024 * 1024;
+
+/// Longest header number accepted (u32 fits in 10 digits)
+const MAX_TOKEN_DIGITS: usize = 10;
+
+/// Hardened PNM decoder configuration
+#[derive(Debug, Clone)]
+pub struct PnmDecoderConfig {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_file_size: usize,
+}
+
+impl Default for PnmDecoderConfig {
+    fn default() -> Self {
+        Self {
+            max_width: MAX_DIMENSION,
+            max_height: MAX_DIMENSION,
+            max_file_size: MAX_FILE_SIZE,
+        }
+    }
+}
+
+/// Netpbm variant, from the `P1`..`P6` magic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PnmKind {
+    Bitmap { binary: bool },
+    Graymap { binary: bool },
+    Pixmap { binary: bool },
+}
+
+impl PnmKind {
+    fn samples_per_pixel(self) -> usize {
+        match self {
+            PnmKind::Pixmap { .. } => 3,
+            _ => 1,
+        }
+    }
+}
+
+/// True for a `P1`..`P6` magic followed by whitespace
+pub fn is_pnm(data: &[u8]) -> bool {
+    data.len() >= 3
+        && data[0] == b'P'
+        && (b'1'..=b'6').contains(&data[1])
+        && data[2].is_ascii_whitespace()
+}
+
+/// Decode a PBM/PGM/PPM image (P1-P6) to 8-bit RGBA
+pub fn decode_pnm(data: &[u8]) -> Result<DecodedImage, ImageHardenError> {
+    decode_pnm_with_config(data, &PnmDecoderConfig::default())
+}
+
+/// Decode PNM with custom configuration
+///
+/// Only the first image of a multi-image file is decoded. Samples are
+/// rescaled from maxval to 0..=255; bitmaps map 1 to black.
+pub fn decode_pnm_with_config(
+    data: &[u8],
+    config: &PnmDecoderConfig,
+) -> Result<DecodedImage, ImageHardenError> {
+    if data.len() > config.max_file_size {
+        return Err(ImageHardenError::PnmError(format!(
+            "File size {} exceeds maximum {}",
+            data.len(),
+            config.max_file_size
+        )));
+    }
+    if !is_pnm(data) {
+        return Err(ImageHardenError::PnmError(
+            "Invalid PNM magic bytes".to_string(),
+        ));
+    }
+    let kind = match data[1] {
+        b'1' => PnmKind::Bitmap { binary: false },
+        b'2' => PnmKind::Graymap { binary: false },
+        b'3' => PnmKind::Pixmap { binary: false },
+        b'4' => PnmKind::Bitmap { binary: true },
+        b'5' => PnmKind::Graymap { binary: true },
+        _ => PnmKind::Pixmap { binary: true },
+    };
+
+    let mut reader = PnmReader { data, pos: 2 };
+    let width = reader.header_number()?;
+    let height = reader.header_number()?;
+    let maxval = match kind {
+        PnmKind::Bitmap { .. } => 1,
+        _ => reader.header_number()?,
+    };
+
+    check_nonzero_dimensions(width, height)?;
+    if width > config.max_width || height > config.max_height {
+        return Err(ImageHardenError::PnmError(format!(
+            "Dimensions {}x{} exceed maximum {}x{}",
+            width, height, config.max_width, config.max_height
+        )));
+    }
+    if maxval == 0 || maxval > 65535 {
+        return Err(ImageHardenError::PnmError(format!(
+            "Invalid maxval {}",
+            maxval
+        )));
+    }
+    let out_len = check_pixel_budget(width, height, 4)?;
+
+    let pixels = width as usize * height as usize;
+    let samples = pixels * kind.samples_per_pixel();
+    let scale = |v: u32| ((v * 255 + maxval / 2) / maxval) as u8;
+
+    // Every sample takes at least one byte, so an implausibly large header
+    // fails here before the sample buffer is allocated
+    let min_raster = match kind {
+        PnmKind::Bitmap { binary: true } => (width as usize + 7) / 8 * height as usize,
+        _ => samples,
+    };
+    if reader.remaining() < min_raster {
+        return Err(ImageHardenError::PnmError(format!(
+            "Raster needs at least {} bytes, {} available",
+            min_raster,
+            reader.remaining()
+        )));
+    }
+    let mut values = Vec::with_capacity(samples);
+
+    match kind {
+        PnmKind::Bitmap { binary: true } => {
+            reader.raster_start()?;
+            let row_bytes = (width as usize + 7) / 8;
+            let raster = reader.take(row_bytes * height as usize)?;
+            for row in raster.chunks_exact(row_bytes) {
+                for x in 0..width as usize {
+                    values.push(if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                        0
+                    } else {
+                        255
+                    });
+                }
+            }
+        }
+        PnmKind::Bitmap { binary: false } => {
+            // Plain PBM digits need no separating whitespace
+            for _ in 0..samples {
+                values.push(if reader.next_bit()? { 0 } else { 255 });
+            }
+        }
+        PnmKind::Graymap { binary: true } | PnmKind::Pixmap { binary: true } => {
+            reader.raster_start()?;
+            let wide = maxval > 255;
+            let raster = reader.take(samples * if wide { 2 } else { 1 })?;
+            if wide {
+                for pair in raster.chunks_exact(2) {
+                    let v = u16::from_be_bytes([pair[0], pair[1]]) as u32;
+                    values.push(scale(check_sample(v, maxval)?));
+                }
+            } else {
+                for &v in raster {
+                    values.push(scale(check_sample(v as u32, maxval)?));
+                }
+            }
+        }
+        PnmKind::Graymap { binary: false } | PnmKind::Pixmap { binary: false } => {
+            for _ in 0..samples {
+                values.push(scale(check_sample(reader.number()?, maxval)?));
+            }
+        }
+    }
+
+    let mut rgba = Vec::with_capacity(out_len);
+    if kind.samples_per_pixel() == 3 {
+        for px in values.chunks_exact(3) {
+            rgba.extend_from_slice(&[px[0], px[1], px[2], 255]);
+        }
+    } else {
+        for &v in &values {
+            rgba.extend_from_slice(&[v, v, v, 255]);
+        }
+    }
+
+    Ok(DecodedImage {
+        data: rgba,
+        width,
+        height,
+        channels: 4,
+        bit_depth: 8,
+        had_alpha: false,
+        icc_profile: None,
+    })
+}
+
+fn check_sample(value: u32, maxval: u32) -> Result<u32, ImageHardenError> {
+    if value > maxval {
+        return Err(ImageHardenError::PnmError(format!(
+            "Sample {} exceeds maxval {}",
+            value, maxval
+        )));
+    }
+    Ok(value)
+}
+
+/// Cursor over the header and ASCII raster tokens
+struct PnmReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PnmReader<'a> {
+    /// Skip whitespace and `#` comments, which run to the end of the line
+    fn skip_separators(&mut self) {
+        while let Some(&b) = self.data.get(self.pos) {
+            if b == b'#' {
+                while matches!(self.data.get(self.pos), Some(&c) if c != b'\n' && c != b'\r') {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// A header field must be followed by whitespace or a comment
+    fn header_number(&mut self) -> Result<u32, ImageHardenError> {
+        let value = self.number()?;
+        match self.data.get(self.pos) {
+            Some(b) if b.is_ascii_whitespace() || *b == b'#' => Ok(value),
+            _ => Err(ImageHardenError::PnmError(
+                "Truncated or malformed PNM header".to_string(),
+            )),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, ImageHardenError> {
+        self.skip_separators();
+        let start = self.pos;
+        while matches!(self.data.get(self.pos), Some(b) if b.is_ascii_digit()) {
+            self.pos += 1;
+            if self.pos - start > MAX_TOKEN_DIGITS {
+                return Err(ImageHardenError::PnmError(
+                    "PNM number too long".to_string(),
+                ));
+            }
+        }
+        if self.pos == start {
+            return Err(ImageHardenError::PnmError(format!(
+                "Expected a number at offset {}",
+                start
+            )));
+        }
+        // At most 10 ASCII digits, so only the u32 range can fail
+        std::str::from_utf8(&self.data[start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or_else(|| ImageHardenError::PnmError("PNM number out of range".to_string()))
+    }
+
+    fn next_bit(&mut self) -> Result<bool, ImageHardenError> {
+        self.skip_separators();
+        let bit = match self.data.get(self.pos) {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => {
+                return Err(ImageHardenError::PnmError(format!(
+                    "Expected a bitmap digit at offset {}",
+                    self.pos
+                )))
+            }
+        };
+        self.pos += 1;
+        Ok(bit)
+    }
+
+    /// Binary rasters start after exactly one whitespace byte
+    fn raster_start(&mut self) -> Result<(), ImageHardenError> {
+        match self.data.get(self.pos) {
+            Some(b) if b.is_ascii_whitespace() => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(ImageHardenError::PnmError(
+                "Missing whitespace before PNM raster".to_string(),
+            )),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ImageHardenError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| {
+                ImageHardenError::PnmError(format!(
+                    "Raster needs {} bytes, {} available",
+                    len,
+                    self.remaining()
+                ))
+            })?;
+        let raster = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(raster)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_p3() {
+        let data = b"P3\n# two pixels\n2 1\n255\n255 0 0   0 128 255\n";
+        let image = decode_pnm(data).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 1, 4));
+        assert_eq!(image.data, [255, 0, 0, 255, 0, 128, 255, 255]);
+
+        // Samples above maxval are rejected
+        assert!(decode_pnm(b"P3\n1 1\n15\n16 0 0\n").is_err());
+        // Too few samples
+        assert!(decode_pnm(b"P3\n2 1\n255\n1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn test_binary_p6() {
+        let mut data = b"P6 2 2 255\n".to_vec();
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let image = decode_pnm(&data).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(&image.data[..8], &[1, 2, 3, 255, 4, 5, 6, 255]);
+
+        // Truncated raster
+        assert!(decode_pnm(&data[..data.len() - 1]).is_err());
+
+        // 16-bit samples rescale from maxval
+        let wide = [
+            &b"P6 1 1 65535\n"[..],
+            &[0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00],
+        ]
+        .concat();
+        assert_eq!(decode_pnm(&wide).unwrap().data, [255, 128, 0, 255]);
+    }
+
+    #[test]
+    fn test_bitmaps_and_graymaps() {
+        // Plain PBM digits may run together; 1 is black
+        let pbm = decode_pnm(b"P1\n3 1\n101\n").unwrap();
+        assert_eq!(pbm.data, [0, 0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 255]);
+
+        let packed = decode_pnm(&[&b"P4\n3 1\n"[..], &[0b0100_0000]].concat()).unwrap();
+        assert_eq!(
+            packed.data,
+            [255, 255, 255, 255, 0, 0, 0, 255, 255, 255, 255, 255]
+        );
+
+        let pgm = decode_pnm(&[&b"P5\n1 1\n3\n"[..], &[3]].concat()).unwrap();
+        assert_eq!(pgm.data, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_rejects_bad_headers() {
+        assert!(decode_pnm(b"P7\n1 1\n255\n").is_err());
+        assert!(decode_pnm(b"P6\n0 1\n255\n").is_err());
+        assert!(decode_pnm(b"P6\n1 1\n0\n\0").is_err());
+        assert!(decode_pnm(b"P6\n1 1\n70000\n\0\0\0\0\0\0").is_err());
+        assert!(decode_pnm(b"P6\n99999999999 1\n255\n").is_err());
+        assert!(decode_pnm(b"P6\n1 1\n255").is_err());
+        assert!(matches!(
+            decode_pnm(b"P6\n20000 1\n255\n"),
+            Err(ImageHardenError::PnmError(_))
+        ));
+    }
+}
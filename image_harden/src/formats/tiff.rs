@@ -3,12 +3,16 @@
 ///! Security measures:
 ///! - Strict dimension limits (max 16384x16384)
 ///! - File size caps (max 500 MB - TIFF can be large)
-///! - Maximum IFD (Image File Directory) count limits
+///! - Maximum IFD (Image File Directory) count limits; IFD loops hit the cap
+///! - Page index validated against the IFD chain before any pixel work
 ///! - Memory quota enforcement
 ///! - Magic byte validation (II\x2A\x00 or MM\x00\x2A)
+///! - DNG previews decoded only through the hardened JPEG decoder
 ///! - Fail-closed error handling
 
-use crate::ImageHardenError;
+use crate::{
+    check_nonzero_dimensions, check_pixel_budget, decode_jpeg_full, DecodedImage, ImageHardenError,
+};
 
 /// Maximum allowed TIFF image dimensions
 const MAX_DIMENSION: u32 = 16384;
@@ -52,11 +56,55 @@ pub fn decode_tiff(data: &[u8]) -> Result<Vec<u8>, ImageHardenError> {
     decode_tiff_with_config(data, &TiffDecoderConfig::default())
 }
 
-/// Decode TIFF with custom configuration
+/// Decode TIFF with custom configuration (first page only)
 pub fn decode_tiff_with_config(
     data: &[u8],
     config: &TiffDecoderConfig,
 ) -> Result<Vec<u8>, ImageHardenError> {
+    decode_tiff_page(data, 0, config)
+}
+
+/// Decode the first page with its geometry and sample layout
+pub fn decode_tiff_full(data: &[u8]) -> Result<DecodedImage, ImageHardenError> {
+    let config = TiffDecoderConfig::default();
+    check_tiff_input(data, &config)?;
+    let ifd = ifd_offsets(data, &config)?[0];
+    decode_ifd(&TiffReader::new(data), ifd, &config)
+}
+
+/// Number of pages (IFDs) in a TIFF, up to the default `max_ifd_count`
+pub fn tiff_page_count(data: &[u8]) -> Result<usize, ImageHardenError> {
+    let config = TiffDecoderConfig::default();
+    check_tiff_input(data, &config)?;
+    Ok(ifd_offsets(data, &config)?.len())
+}
+
+/// Decode one page (the `page`th IFD, from 0) of a multi-page TIFF
+///
+/// Only the selected IFD's strips are read. Pages must be uncompressed,
+/// chunky 8-bit gray, gray+alpha, RGB, or RGBA; samples are returned as
+/// stored (WhiteIsZero gray is inverted).
+pub fn decode_tiff_page(
+    data: &[u8],
+    page: usize,
+    config: &TiffDecoderConfig,
+) -> Result<Vec<u8>, ImageHardenError> {
+    check_tiff_input(data, config)?;
+
+    let offsets = ifd_offsets(data, config)?;
+    let ifd = *offsets.get(page).ok_or_else(|| {
+        ImageHardenError::TiffError(format!(
+            "Page {} out of range ({} pages)",
+            page,
+            offsets.len()
+        ))
+    })?;
+
+    decode_ifd(&TiffReader::new(data), ifd, config).map(|image| image.data)
+}
+
+/// Size, magic, and emptiness checks shared by every entry point
+fn check_tiff_input(data: &[u8], config: &TiffDecoderConfig) -> Result<(), ImageHardenError> {
     // Input validation
     if data.is_empty() {
         return Err(ImageHardenError::TiffError(
@@ -88,22 +136,373 @@ pub fn decode_tiff_with_config(
         ));
     }
 
-    // TODO: Implement actual libtiff FFI decoding
-    // For now, return placeholder
-    // In production, this would:
-    // 1. Open TIFF from memory with TIFFClientOpen
-    // 2. Count IFDs and validate against max_ifd_count
-    // 3. For each IFD:
-    //    a. Read dimensions with TIFFGetField
-    //    b. Validate dimensions against config
-    //    c. Estimate memory usage
-    //    d. Read image data with TIFFReadRGBAImage or TIFFReadEncodedStrip
-    // 4. Close TIFF with TIFFClose
-    // 5. Return decoded data
+    Ok(())
+}
+
+/// TIFF tags read by the page decoder
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_PLANAR_CONFIG: u16 = 284;
+
+/// Tags DNG files use to locate the raw image and its previews
+const TAG_NEW_SUBFILE_TYPE: u16 = 254;
+const TAG_SUB_IFDS: u16 = 330;
+const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 513;
+const TAG_JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 514;
+const TAG_DNG_VERSION: u16 = 0xC612;
+
+/// Compression values whose strips hold a JPEG stream (old-style and new)
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+
+/// Bounds-checked reads in the file's byte order
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            little_endian: data.starts_with(TIFF_MAGIC_LE),
+        }
+    }
+
+    fn bytes<const N: usize>(&self, pos: usize) -> Result<[u8; N], ImageHardenError> {
+        pos.checked_add(N)
+            .and_then(|end| self.data.get(pos..end))
+            .map(|bytes| bytes.try_into().unwrap())
+            .ok_or_else(|| {
+                ImageHardenError::TiffError(format!("Read past end of file at offset {}", pos))
+            })
+    }
 
-    Err(ImageHardenError::TiffError(
-        "TIFF decoding not yet implemented - requires libtiff FFI".to_string(),
-    ))
+    fn u16(&self, pos: usize) -> Result<u16, ImageHardenError> {
+        let bytes = self.bytes::<2>(pos)?;
+        Ok(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32, ImageHardenError> {
+        let bytes = self.bytes::<4>(pos)?;
+        Ok(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+}
+
+/// Offsets of every IFD in the chain, refusing more than `max_ifd_count`
+/// (which also ends IFD loops)
+fn ifd_offsets(data: &[u8], config: &TiffDecoderConfig) -> Result<Vec<usize>, ImageHardenError> {
+    let reader = TiffReader::new(data);
+    let mut offsets = Vec::new();
+    let mut offset = reader.u32(4)? as usize;
+    while offset != 0 {
+        if offsets.len() == config.max_ifd_count {
+            return Err(ImageHardenError::TiffError(format!(
+                "More than {} IFDs",
+                config.max_ifd_count
+            )));
+        }
+        let entries = reader.u16(offset)? as usize;
+        // Entry table plus the next-IFD offset must be inside the file
+        let next = reader.u32(offset.saturating_add(2 + entries * 12))? as usize;
+        offsets.push(offset);
+        offset = next;
+    }
+    if offsets.is_empty() {
+        return Err(ImageHardenError::TiffError("TIFF has no IFDs".to_string()));
+    }
+    Ok(offsets)
+}
+
+/// BYTE, SHORT, LONG, or IFD values of `tag` in the IFD at `ifd`, or None
+/// if absent
+fn ifd_values(
+    reader: &TiffReader,
+    ifd: usize,
+    tag: u16,
+) -> Result<Option<Vec<u32>>, ImageHardenError> {
+    let entries = reader.u16(ifd)? as usize;
+    for index in 0..entries {
+        let entry = ifd + 2 + index * 12;
+        if reader.u16(entry)? != tag {
+            continue;
+        }
+        let value_size = match reader.u16(entry + 2)? {
+            1 => 1,
+            3 => 2,
+            4 | 13 => 4,
+            other => {
+                return Err(ImageHardenError::TiffError(format!(
+                    "Tag {} has unsupported type {}",
+                    tag, other
+                )))
+            }
+        };
+        let count = reader.u32(entry + 4)? as usize;
+        let total = count
+            .checked_mul(value_size)
+            .filter(|&len| len <= reader.data.len());
+        let total = total.ok_or_else(|| {
+            ImageHardenError::TiffError(format!("Tag {} count {} too large", tag, count))
+        })?;
+        // Values of four bytes or less are stored inline
+        let start = if total <= 4 {
+            entry + 8
+        } else {
+            reader.u32(entry + 8)? as usize
+        };
+
+        let mut values = Vec::with_capacity(count);
+        for i in 0..count {
+            let pos = start + i * value_size;
+            values.push(match value_size {
+                1 => reader.bytes::<1>(pos)?[0] as u32,
+                2 => reader.u16(pos)? as u32,
+                _ => reader.u32(pos)?,
+            });
+        }
+        return Ok(Some(values));
+    }
+    Ok(None)
+}
+
+/// Single value of `tag`, falling back to `default` when absent
+fn ifd_value(
+    reader: &TiffReader,
+    ifd: usize,
+    tag: u16,
+    default: Option<u32>,
+) -> Result<u32, ImageHardenError> {
+    match ifd_values(reader, ifd, tag)? {
+        Some(values) if !values.is_empty() => Ok(values[0]),
+        _ => default
+            .ok_or_else(|| ImageHardenError::TiffError(format!("Missing required tag {}", tag))),
+    }
+}
+
+/// Decode the uncompressed strips of one IFD
+fn decode_ifd(
+    reader: &TiffReader,
+    ifd: usize,
+    config: &TiffDecoderConfig,
+) -> Result<DecodedImage, ImageHardenError> {
+    let width = ifd_value(reader, ifd, TAG_IMAGE_WIDTH, None)?;
+    let height = ifd_value(reader, ifd, TAG_IMAGE_LENGTH, None)?;
+    check_nonzero_dimensions(width, height)?;
+    if width > config.max_width || height > config.max_height {
+        return Err(ImageHardenError::TiffError(format!(
+            "Dimensions {}x{} exceed maximum {}x{}",
+            width, height, config.max_width, config.max_height
+        )));
+    }
+
+    let compression = ifd_value(reader, ifd, TAG_COMPRESSION, Some(1))?;
+    if compression != 1 {
+        return Err(ImageHardenError::TiffError(format!(
+            "Compression {} requires libtiff, which is not wired up yet",
+            compression
+        )));
+    }
+    let samples = ifd_value(reader, ifd, TAG_SAMPLES_PER_PIXEL, Some(1))?;
+    if !(1..=4).contains(&samples) {
+        return Err(ImageHardenError::TiffError(format!(
+            "Unsupported samples per pixel: {}",
+            samples
+        )));
+    }
+    let bits = ifd_values(reader, ifd, TAG_BITS_PER_SAMPLE)?.unwrap_or_else(|| vec![1]);
+    if bits.len() != samples as usize || bits.iter().any(|&b| b != 8) {
+        return Err(ImageHardenError::TiffError(format!(
+            "Unsupported bits per sample: {:?}",
+            bits
+        )));
+    }
+    if samples > 1 && ifd_value(reader, ifd, TAG_PLANAR_CONFIG, Some(1))? != 1 {
+        return Err(ImageHardenError::TiffError(
+            "Planar (separate) sample layout is not supported".to_string(),
+        ));
+    }
+    let photometric = ifd_value(reader, ifd, TAG_PHOTOMETRIC, None)?;
+    match (photometric, samples) {
+        (0 | 1, 1 | 2) | (2, 3 | 4) => {}
+        _ => {
+            return Err(ImageHardenError::TiffError(format!(
+                "Unsupported photometric {} with {} samples",
+                photometric, samples
+            )))
+        }
+    }
+
+    let output_len = check_pixel_budget(width, height, samples)?;
+    let row_bytes = width as usize * samples as usize;
+    let rows_per_strip =
+        ifd_value(reader, ifd, TAG_ROWS_PER_STRIP, Some(height))?.clamp(1, height) as usize;
+    let strip_count = (height as usize).div_ceil(rows_per_strip);
+    let offsets = ifd_values(reader, ifd, TAG_STRIP_OFFSETS)?.unwrap_or_default();
+    let byte_counts = ifd_values(reader, ifd, TAG_STRIP_BYTE_COUNTS)?.unwrap_or_default();
+    if offsets.len() != strip_count || byte_counts.len() != strip_count {
+        return Err(ImageHardenError::TiffError(format!(
+            "Expected {} strips, found {} offsets and {} byte counts",
+            strip_count,
+            offsets.len(),
+            byte_counts.len()
+        )));
+    }
+
+    let mut out = Vec::with_capacity(output_len);
+    for (strip, (&offset, &byte_count)) in offsets.iter().zip(&byte_counts).enumerate() {
+        let rows = rows_per_strip.min(height as usize - strip * rows_per_strip);
+        let len = rows * row_bytes;
+        if (byte_count as usize) < len {
+            return Err(ImageHardenError::TiffError(format!(
+                "Strip {} holds {} bytes, {} needed",
+                strip, byte_count, len
+            )));
+        }
+        let start = offset as usize;
+        let bytes = start
+            .checked_add(len)
+            .and_then(|end| reader.data.get(start..end))
+            .ok_or_else(|| {
+                ImageHardenError::TiffError(format!("Strip {} extends past end of file", strip))
+            })?;
+        out.extend_from_slice(bytes);
+    }
+
+    // WhiteIsZero: 0 is white; flip the gray channel to the usual sense
+    if photometric == 0 {
+        for pixel in out.chunks_exact_mut(samples as usize) {
+            pixel[0] = 255 - pixel[0];
+        }
+    }
+    Ok(DecodedImage {
+        data: out,
+        width,
+        height,
+        channels: samples as u8,
+        bit_depth: 8,
+        had_alpha: samples == 2 || samples == 4,
+        icc_profile: None,
+    })
+}
+
+/// What `validate_dng` found in a DNG
+#[derive(Debug, Clone)]
+pub struct DngInfo {
+    /// DNGVersion bytes, e.g. [1, 4, 0, 0] for DNG 1.4
+    pub version: [u8; 4],
+    /// Size of the first full-resolution (raw) IFD, if there is one
+    pub raw_dimensions: Option<(u32, u32)>,
+    /// Largest JPEG preview, decoded by the hardened JPEG decoder
+    pub preview: Option<DecodedImage>,
+}
+
+/// Validate a DNG's IFD structure and decode its embedded JPEG preview
+///
+/// Walks the IFD chain plus one level of SubIFDs, with `max_ifd_count`
+/// covering both. Raw data is never developed. Of the reduced-resolution
+/// IFDs holding a JPEG stream, the largest is decoded with the hardened
+/// JPEG decoder, and a failure there fails the whole call.
+pub fn validate_dng(data: &[u8]) -> Result<DngInfo, ImageHardenError> {
+    let config = TiffDecoderConfig::default();
+    check_tiff_input(data, &config)?;
+    let reader = TiffReader::new(data);
+    let top = ifd_offsets(data, &config)?;
+
+    let version = match ifd_values(&reader, top[0], TAG_DNG_VERSION)?.as_deref() {
+        Some(&[a, b, c, d]) => [a as u8, b as u8, c as u8, d as u8],
+        _ => {
+            return Err(ImageHardenError::TiffError(
+                "Not a DNG: IFD0 has no DNGVersion tag".to_string(),
+            ))
+        }
+    };
+
+    let mut ifds = top.clone();
+    for &ifd in &top {
+        for sub_ifd in ifd_values(&reader, ifd, TAG_SUB_IFDS)?.unwrap_or_default() {
+            if ifds.len() == config.max_ifd_count {
+                return Err(ImageHardenError::TiffError(format!(
+                    "More than {} IFDs",
+                    config.max_ifd_count
+                )));
+            }
+            ifds.push(sub_ifd as usize);
+        }
+    }
+
+    let mut raw_dimensions = None;
+    let mut largest_preview: Option<(u64, &[u8])> = None;
+    for &ifd in &ifds {
+        let width = ifd_value(&reader, ifd, TAG_IMAGE_WIDTH, None)?;
+        let height = ifd_value(&reader, ifd, TAG_IMAGE_LENGTH, None)?;
+        // Bit 0 of NewSubfileType marks a reduced-resolution copy
+        if ifd_value(&reader, ifd, TAG_NEW_SUBFILE_TYPE, Some(0))? & 1 == 0 {
+            raw_dimensions.get_or_insert((width, height));
+            continue;
+        }
+        let compression = ifd_value(&reader, ifd, TAG_COMPRESSION, Some(1))?;
+        if !matches!(compression, COMPRESSION_OLD_JPEG | COMPRESSION_JPEG) {
+            continue;
+        }
+        let area = width as u64 * height as u64;
+        if largest_preview.is_none_or(|(largest, _)| area > largest) {
+            largest_preview = Some((area, jpeg_stream(&reader, ifd)?));
+        }
+    }
+
+    Ok(DngInfo {
+        version,
+        raw_dimensions,
+        preview: largest_preview
+            .map(|(_, stream)| decode_jpeg_full(stream))
+            .transpose()?,
+    })
+}
+
+/// The single JPEG stream of an IFD: JPEGInterchangeFormat, else one strip
+fn jpeg_stream<'a>(reader: &TiffReader<'a>, ifd: usize) -> Result<&'a [u8], ImageHardenError> {
+    let (offsets, lengths) = match ifd_values(reader, ifd, TAG_JPEG_INTERCHANGE_FORMAT)? {
+        Some(offsets) => (
+            offsets,
+            ifd_values(reader, ifd, TAG_JPEG_INTERCHANGE_FORMAT_LENGTH)?,
+        ),
+        None => (
+            ifd_values(reader, ifd, TAG_STRIP_OFFSETS)?.unwrap_or_default(),
+            ifd_values(reader, ifd, TAG_STRIP_BYTE_COUNTS)?,
+        ),
+    };
+    let (&[offset], Some(&[len])) = (offsets.as_slice(), lengths.as_deref()) else {
+        return Err(ImageHardenError::TiffError(format!(
+            "Preview IFD at offset {} does not hold exactly one JPEG stream",
+            ifd
+        )));
+    };
+    let start = offset as usize;
+    start
+        .checked_add(len as usize)
+        .and_then(|end| reader.data.get(start..end))
+        .ok_or_else(|| {
+            ImageHardenError::TiffError(format!(
+                "JPEG preview at offset {} extends past end of file",
+                start
+            ))
+        })
 }
 
 /// Validate TIFF file without full decode
@@ -153,6 +552,130 @@ mod tests {
         assert!(result.is_err());
     }
 
+    /// Little-endian TIFF with one uncompressed 2x2 gray page per value
+    fn multi_page_tiff(values: &[u8]) -> Vec<u8> {
+        let mut data = Vec::from(TIFF_MAGIC_LE);
+        data.extend_from_slice(&8u32.to_le_bytes());
+        for (index, &value) in values.iter().enumerate() {
+            let ifd = data.len();
+            let entries: [(u16, u16, u32); 8] = [
+                (TAG_IMAGE_WIDTH, 3, 2),
+                (TAG_IMAGE_LENGTH, 3, 2),
+                (TAG_BITS_PER_SAMPLE, 3, 8),
+                (TAG_COMPRESSION, 3, 1),
+                (TAG_PHOTOMETRIC, 3, 1),
+                (TAG_STRIP_OFFSETS, 4, (ifd + 2 + 8 * 12 + 4) as u32),
+                (TAG_ROWS_PER_STRIP, 3, 2),
+                (TAG_STRIP_BYTE_COUNTS, 4, 4),
+            ];
+            data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for (tag, kind, value) in entries {
+                data.extend_from_slice(&tag.to_le_bytes());
+                data.extend_from_slice(&kind.to_le_bytes());
+                data.extend_from_slice(&1u32.to_le_bytes());
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            let next = if index + 1 < values.len() {
+                data.len() + 4 + 4
+            } else {
+                0
+            };
+            data.extend_from_slice(&(next as u32).to_le_bytes());
+            data.extend_from_slice(&[value; 4]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_multi_page_selection() {
+        let data = multi_page_tiff(&[10, 20, 30]);
+        assert_eq!(tiff_page_count(&data).unwrap(), 3);
+
+        let config = TiffDecoderConfig::default();
+        assert_eq!(decode_tiff_page(&data, 1, &config).unwrap(), [20; 4]);
+        assert_eq!(decode_tiff(&data).unwrap(), [10; 4]);
+        assert!(matches!(
+            decode_tiff_page(&data, 3, &config),
+            Err(ImageHardenError::TiffError(_))
+        ));
+
+        // An IFD chain over the cap (or a loop) is refused
+        let capped = TiffDecoderConfig {
+            max_ifd_count: 2,
+            ..TiffDecoderConfig::default()
+        };
+        assert!(decode_tiff_page(&data, 0, &capped).is_err());
+        let mut looped = multi_page_tiff(&[10]);
+        let next = 8 + 2 + 8 * 12;
+        looped[next..next + 4].copy_from_slice(&8u32.to_le_bytes());
+        assert!(tiff_page_count(&looped).is_err());
+    }
+
+    /// Little-endian IFD from (tag, type, count, inline value) entries
+    fn ifd(entries: &[(u16, u16, u32, u32)], next: u32) -> Vec<u8> {
+        let mut out = (entries.len() as u16).to_le_bytes().to_vec();
+        for &(tag, kind, count, value) in entries {
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&kind.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&next.to_le_bytes());
+        out
+    }
+
+    /// DNG 1.4 with an 8x8 JPEG preview in IFD0 and a 16x16 raw SubIFD
+    fn dng(jpeg: &[u8]) -> Vec<u8> {
+        // Header, 9-entry IFD0, then 4-entry SubIFD, then the JPEG
+        let sub_ifd = 8 + 2 + 9 * 12 + 4;
+        let jpeg_offset = sub_ifd + 2 + 4 * 12 + 4;
+        let mut data = Vec::from(TIFF_MAGIC_LE);
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend(ifd(
+            &[
+                (TAG_NEW_SUBFILE_TYPE, 4, 1, 1),
+                (TAG_IMAGE_WIDTH, 3, 1, 8),
+                (TAG_IMAGE_LENGTH, 3, 1, 8),
+                (TAG_COMPRESSION, 3, 1, COMPRESSION_JPEG),
+                (TAG_PHOTOMETRIC, 3, 1, 6),
+                (TAG_STRIP_OFFSETS, 4, 1, jpeg_offset as u32),
+                (TAG_STRIP_BYTE_COUNTS, 4, 1, jpeg.len() as u32),
+                (TAG_SUB_IFDS, 4, 1, sub_ifd as u32),
+                (TAG_DNG_VERSION, 1, 4, u32::from_le_bytes([1, 4, 0, 0])),
+            ],
+            0,
+        ));
+        data.extend(ifd(
+            &[
+                (TAG_NEW_SUBFILE_TYPE, 4, 1, 0),
+                (TAG_IMAGE_WIDTH, 3, 1, 16),
+                (TAG_IMAGE_LENGTH, 3, 1, 16),
+                (TAG_COMPRESSION, 3, 1, COMPRESSION_JPEG),
+            ],
+            0,
+        ));
+        data.extend_from_slice(jpeg);
+        data
+    }
+
+    #[test]
+    fn test_validate_dng_jpeg_preview() {
+        let data = dng(&crate::test_support::JPEG_RGB_8X8);
+        let info = validate_dng(&data).unwrap();
+        assert_eq!(info.version, [1, 4, 0, 0]);
+        assert_eq!(info.raw_dimensions, Some((16, 16)));
+        let preview = info.preview.unwrap();
+        assert_eq!((preview.width, preview.height, preview.channels), (8, 8, 3));
+
+        // Plain TIFF, a preview running past EOF, and a corrupt preview
+        assert!(validate_dng(&multi_page_tiff(&[10])).is_err());
+        assert!(validate_dng(&data[..data.len() - 1]).is_err());
+        assert!(matches!(
+            validate_dng(&dng(&[0; 64])),
+            Err(ImageHardenError::JpegError(_))
+        ));
+    }
+
     #[test]
     fn test_little_endian_magic() {
         let mut data = Vec::from(TIFF_MAGIC_LE);
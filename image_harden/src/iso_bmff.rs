@@ -0,0 +1,303 @@
+///! Bounds-checked ISO-BMFF box walking (MP4/MOV, HEIF, AVIF)
+///!
+///! Every ftyp-based format goes through the same header parser, so brand
+///! detection, nesting checks, and metadata stripping agree on where each
+///! box starts and ends. Sizes are checked against the enclosing range before
+///! any offset arithmetic, and child iteration is capped at
+///! MAX_BOX_NESTING_DEPTH.
+use crate::{check_box_depth, ImageHardenError};
+
+/// Maximum compatible brands considered from one ftyp box
+const MAX_FTYP_BRANDS: usize = 64;
+
+/// One box header as found in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IsoBox {
+    pub fourcc: [u8; 4],
+    /// Total size in bytes, header included
+    pub size: usize,
+    /// Offset of the size field in the file
+    pub offset: usize,
+    /// 8, or 16 for a 64-bit (largesize) header
+    pub header_len: usize,
+}
+
+impl IsoBox {
+    pub fn payload_start(&self) -> usize {
+        self.offset + self.header_len
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.size
+    }
+}
+
+/// Parse the box header at `pos`, which must fit in data[pos..end]
+///
+/// A 32-bit size of 1 means a 64-bit largesize follows the type; a size of
+/// 0 means the box runs to end of file, which only the last box may do.
+pub(crate) fn read_box(data: &[u8], pos: usize, end: usize) -> Result<IsoBox, ImageHardenError> {
+    let end = end.min(data.len());
+    let available = end.saturating_sub(pos);
+    if available < 8 {
+        return Err(ImageHardenError::ValidationFailure(format!(
+            "Truncated ISOBMFF box header at offset {}",
+            pos
+        )));
+    }
+    let be32 = |at: usize| u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+
+    let (header_len, size) = match be32(pos) {
+        0 if end == data.len() => (8, available),
+        // A parent ending before EOF cannot hold a child that runs to EOF
+        0 => {
+            return Err(ImageHardenError::ValidationFailure(format!(
+                "ISOBMFF box at offset {} runs to end of file inside a parent box",
+                pos
+            )))
+        }
+        1 => {
+            if available < 16 {
+                return Err(ImageHardenError::ValidationFailure(format!(
+                    "Truncated ISOBMFF largesize header at offset {}",
+                    pos
+                )));
+            }
+            let large = u64::from_be_bytes(data[pos + 8..pos + 16].try_into().unwrap());
+            (16, usize::try_from(large).unwrap_or(usize::MAX))
+        }
+        n => (8, n as usize),
+    };
+    let in_range = pos.checked_add(size).is_some_and(|box_end| box_end <= end);
+    if size < header_len || !in_range {
+        return Err(ImageHardenError::ValidationFailure(format!(
+            "ISOBMFF box size {} out of range at offset {}",
+            size, pos
+        )));
+    }
+
+    Ok(IsoBox {
+        fourcc: data[pos + 4..pos + 8].try_into().unwrap(),
+        size,
+        offset: pos,
+        header_len,
+    })
+}
+
+/// Iterator over sibling boxes in a byte range
+///
+/// Yields `IsoBox`es in file order. A malformed header is yielded once as an
+/// error and ends the iteration, so callers choose between failing and
+/// treating the rest of the range as opaque.
+#[derive(Debug, Clone)]
+pub(crate) struct BoxIter<'a> {
+    data: &'a [u8],
+    pos: usize,
+    end: usize,
+    depth: usize,
+}
+
+impl<'a> BoxIter<'a> {
+    /// Top-level boxes of a whole file
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            end: data.len(),
+            depth: 0,
+        }
+    }
+
+    /// Boxes inside `parent`, after skipping `skip` payload bytes (4 for
+    /// full boxes such as `meta`, whose version/flags precede the children)
+    pub fn children(&self, parent: &IsoBox, skip: usize) -> Result<BoxIter<'a>, ImageHardenError> {
+        let depth = self.depth + 1;
+        check_box_depth(depth)?;
+        Ok(BoxIter {
+            data: self.data,
+            pos: parent
+                .payload_start()
+                .saturating_add(skip)
+                .min(parent.end()),
+            end: parent.end(),
+            depth,
+        })
+    }
+}
+
+impl Iterator for BoxIter<'_> {
+    type Item = Result<IsoBox, ImageHardenError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.end {
+            return None;
+        }
+        match read_box(self.data, self.pos, self.end) {
+            Ok(found) => {
+                self.pos = found.end();
+                Some(Ok(found))
+            }
+            Err(e) => {
+                self.pos = self.end;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Major brand followed by up to MAX_FTYP_BRANDS compatible brands, or
+/// None unless the file starts with a well-formed ftyp box
+pub(crate) fn ftyp_brands(data: &[u8]) -> Option<Vec<[u8; 4]>> {
+    let ftyp = BoxIter::new(data).next()?.ok()?;
+    let payload = &data[ftyp.payload_start()..ftyp.end()];
+    // major_brand, minor_version, then compatible brands
+    if &ftyp.fourcc != b"ftyp" || payload.len() < 8 {
+        return None;
+    }
+    Some(
+        std::iter::once(&payload[..4])
+            .chain(payload[8..].chunks_exact(4).take(MAX_FTYP_BRANDS))
+            .map(|brand| brand.try_into().unwrap())
+            .collect(),
+    )
+}
+
+/// True when the leading ftyp box lists any of `candidates`
+pub(crate) fn has_brand(data: &[u8], candidates: &[&[u8; 4]]) -> bool {
+    ftyp_brands(data).is_some_and(|brands| brands.iter().any(|b| candidates.contains(&b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::mp4_box;
+    use crate::MAX_BOX_NESTING_DEPTH;
+
+    #[test]
+    fn test_sibling_boxes() {
+        let mut data = mp4_box(b"ftyp", b"avif\0\0\0\0mif1");
+        data.extend(mp4_box(b"free", &[0; 3]));
+        let boxes: Vec<_> = BoxIter::new(&data).map(Result::unwrap).collect();
+        assert_eq!(boxes.len(), 2);
+        assert_eq!(
+            (&boxes[0].fourcc, boxes[0].size, boxes[0].offset),
+            (b"ftyp", 20, 0)
+        );
+        assert_eq!(
+            (&boxes[1].fourcc, boxes[1].size, boxes[1].offset),
+            (b"free", 11, 20)
+        );
+        assert_eq!(ftyp_brands(&data).unwrap(), [*b"avif", *b"mif1"]);
+    }
+
+    #[test]
+    fn test_largesize_box() {
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&20u64.to_be_bytes());
+        data.extend_from_slice(&[7; 4]);
+        let found = read_box(&data, 0, data.len()).unwrap();
+        assert_eq!((found.size, found.header_len), (20, 16));
+        assert_eq!(&data[found.payload_start()..found.end()], &[7; 4]);
+
+        // A largesize past the buffer, or one that overflows usize
+        data[8..16].copy_from_slice(&21u64.to_be_bytes());
+        assert!(read_box(&data, 0, data.len()).is_err());
+        data[8..16].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert!(read_box(&data, 0, data.len()).is_err());
+        assert!(read_box(&data[..12], 0, 12).is_err());
+    }
+
+    #[test]
+    fn test_largesize_siblings() {
+        // 64-bit mdat between two ordinary boxes
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&24u64.to_be_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend(mp4_box(b"free", &[]));
+        let boxes: Vec<_> = BoxIter::new(&data).map(Result::unwrap).collect();
+        let kinds: Vec<_> = boxes
+            .iter()
+            .map(|b| (&b.fourcc, b.offset, b.size))
+            .collect();
+        assert_eq!(
+            kinds,
+            [(b"ftyp", 0, 16), (b"mdat", 16, 24), (b"free", 40, 8)]
+        );
+        assert_eq!(boxes[1].payload_start(), 32);
+    }
+
+    #[test]
+    fn test_zero_size_box() {
+        // Runs to end of file as the last top-level box
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&[0; 5]);
+        let boxes: Vec<_> = BoxIter::new(&data).map(Result::unwrap).collect();
+        assert_eq!((&boxes[1].fourcc, boxes[1].size), (b"mdat", 13));
+
+        // Inside a parent with a sibling after it, it would swallow the sibling
+        let mut inner = 0u32.to_be_bytes().to_vec();
+        inner.extend_from_slice(b"udta");
+        let mut data = mp4_box(b"moov", &inner);
+        data.extend(mp4_box(b"free", &[]));
+        let mut boxes = BoxIter::new(&data);
+        let moov = boxes.next().unwrap().unwrap();
+        let mut children = boxes.children(&moov, 0).unwrap();
+        assert!(children.next().unwrap().is_err());
+        assert!(matches!(
+            read_box(&data, 8, moov.end()),
+            Err(ImageHardenError::ValidationFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_box_ends_iteration() {
+        let mut data = mp4_box(b"free", &[0; 4]);
+        data.extend(mp4_box(b"mdat", &[0; 16]));
+        data.truncate(20);
+        let mut boxes = BoxIter::new(&data);
+        assert!(boxes.next().unwrap().is_ok());
+        assert!(boxes.next().unwrap().is_err());
+        assert!(boxes.next().is_none());
+
+        // Header shorter than 8 bytes, and a size below the header length
+        assert!(read_box(&data, 0, 7).is_err());
+        assert!(read_box(&mp4_box(b"free", &[])[..4], 0, 4).is_err());
+        let mut tiny = mp4_box(b"free", &[]);
+        tiny[3] = 4;
+        assert!(read_box(&tiny, 0, tiny.len()).is_err());
+    }
+
+    #[test]
+    fn test_children_depth_guard() {
+        let mut data = mp4_box(b"free", &[]);
+        for _ in 0..=MAX_BOX_NESTING_DEPTH {
+            data = mp4_box(b"moov", &data);
+        }
+        let mut boxes = BoxIter::new(&data);
+        let mut parent = boxes.next().unwrap().unwrap();
+        for _ in 0..MAX_BOX_NESTING_DEPTH {
+            boxes = boxes.children(&parent, 0).unwrap();
+            parent = boxes.next().unwrap().unwrap();
+        }
+        assert!(boxes.children(&parent, 0).is_err());
+    }
+
+    #[test]
+    fn test_ftyp_brands_need_leading_ftyp() {
+        // The old 20-byte window accepted "ftyp" anywhere near the start
+        let mut data = mp4_box(b"free", b"ftypavif");
+        data.extend(mp4_box(b"ftyp", b"avif\0\0\0\0"));
+        assert_eq!(ftyp_brands(&data), None);
+        assert!(!has_brand(&data, &[b"avif"]));
+        assert_eq!(ftyp_brands(&mp4_box(b"ftyp", b"avif")), None);
+        assert!(has_brand(
+            &mp4_box(b"ftyp", b"mif1\0\0\0\0avif"),
+            &[b"avif"]
+        ));
+    }
+}
@@ -1,7 +1,13 @@
-use image_harden::{decode_jpeg, decode_png, decode_svg, decode_video, ImageHardenError};
+use image_harden::metrics::SECCOMP_VIOLATIONS_TOTAL;
+use image_harden::self_test::run_self_test;
+use image_harden::{
+    decode_jpeg, decode_png, decode_svg, decode_video, read_image_dimensions,
+    validate_video_container, ImageHardenError,
+};
 use landlock::{Access, Landlock, PathFd, Ruleset};
 use libseccomp_rs::{ScmpAction, ScmpFilterContext, ScmpSyscall};
 use nix::sched::{clone, CloneFlags};
+use nix::sys::signal::Signal;
 use nix::sys::wait::{waitpid, WaitStatus};
 use std::env;
 use std::fs::File;
@@ -36,6 +42,7 @@ fn main() {
                     }
                 }
             }
+            "--self-test" => std::process::exit(perform_self_test()),
             "--help" | "-h" => {
                 print_help(&args[0]);
                 return;
@@ -44,11 +51,15 @@ fn main() {
         }
     }
 
-    if args.len() != 2 {
-        eprintln!("Usage: {} <path_to_image>", args[0]);
-        eprintln!("Try '{}  --help' for more information.", args[0]);
-        return;
-    }
+    let (image_path, limits) = match parse_args(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: {} [LIMIT OPTIONS] <path_to_image>", args[0]);
+            eprintln!("Try '{}  --help' for more information.", args[0]);
+            std::process::exit(2);
+        }
+    };
 
     let (read_fd, write_fd) = nix::unistd::pipe().unwrap();
     let mut read_pipe = unsafe { File::from_raw_fd(read_fd) };
@@ -57,15 +68,14 @@ fn main() {
     const STACK_SIZE: usize = 1024 * 1024;
     let mut stack = [0; STACK_SIZE];
 
-    let image_path = &args[1];
-    let file_extension = Path::new(image_path)
+    let file_extension = Path::new(&image_path)
         .extension()
         .and_then(|s| s.to_str())
         .unwrap_or("");
 
     let child_pid = unsafe {
         clone(
-            Box::new(|| child_process(image_path, file_extension, &mut write_pipe)),
+            Box::new(|| child_process(&image_path, file_extension, &limits, &mut write_pipe)),
             &mut stack,
             CloneFlags::CLONE_NEWPID | CloneFlags::CLONE_NEWNET | CloneFlags::CLONE_NEWNS,
             None,
@@ -73,19 +83,155 @@ fn main() {
         .unwrap()
     };
 
-    match waitpid(child_pid, None).unwrap() {
-        WaitStatus::Exited(_, 0) => {
+    match classify_child_exit(waitpid(child_pid, None).unwrap()) {
+        ChildOutcome::Success => {
             let mut result_buf = String::new();
             read_pipe.read_to_string(&mut result_buf).unwrap();
             println!("Successfully decoded image with size: {}", result_buf);
         }
-        _ => {
+        ChildOutcome::SandboxViolation => {
+            SECCOMP_VIOLATIONS_TOTAL.inc();
+            eprintln!("Sandbox violation: decoder killed by seccomp (blocked syscall)");
+        }
+        ChildOutcome::Signaled(signal) => {
+            eprintln!("Decoder terminated by signal {:?}", signal);
+        }
+        ChildOutcome::Failed => {
             eprintln!("Failed to decode image");
         }
     }
 }
 
-fn child_process(image_path: &str, file_extension: &str, write_pipe: &mut File) -> isize {
+// Operator-tuned caps applied on top of the library's built-in limits
+// None keeps the library default for that dimension.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct CliLimits {
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    max_file_size: Option<usize>,
+    max_duration_secs: Option<u64>,
+}
+
+// Split the input path from limit flags (`--max-width 1024` or `--max-width=1024`)
+fn parse_args(args: &[String]) -> Result<(String, CliLimits), String> {
+    let mut limits = CliLimits::default();
+    let mut path = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            if path.replace(arg.clone()).is_some() {
+                return Err("Expected a single input file".to_string());
+            }
+            continue;
+        }
+
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, value.to_string()),
+            None => (
+                arg.as_str(),
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} requires a value", arg))?,
+            ),
+        };
+        match flag {
+            "--max-width" => limits.max_width = Some(parse_limit(flag, &value)?),
+            "--max-height" => limits.max_height = Some(parse_limit(flag, &value)?),
+            "--max-file-size" => limits.max_file_size = Some(parse_limit(flag, &value)?),
+            "--max-duration" => limits.max_duration_secs = Some(parse_limit(flag, &value)?),
+            _ => return Err(format!("Unknown option '{}'", flag)),
+        }
+    }
+
+    path.map(|path| (path, limits))
+        .ok_or_else(|| "Missing input file".to_string())
+}
+
+// Limits must be positive integers; zero would reject every input
+fn parse_limit<T: std::str::FromStr + PartialOrd + Default>(
+    flag: &str,
+    value: &str,
+) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(limit) if limit > T::default() => Ok(limit),
+        _ => Err(format!(
+            "{} expects a positive integer, got '{}'",
+            flag, value
+        )),
+    }
+}
+
+// Enforce the CLI caps against the header geometry (PNG, JPEG) or the
+// validated container metadata (video) before any decoder runs
+fn check_cli_limits(
+    data: &[u8],
+    is_video: bool,
+    limits: &CliLimits,
+) -> Result<(), ImageHardenError> {
+    let geometry_limited = limits.max_width.is_some() || limits.max_height.is_some();
+    let (dimensions, duration_secs) = if is_video {
+        if !geometry_limited && limits.max_duration_secs.is_none() {
+            return Ok(());
+        }
+        let metadata = validate_video_container(data)?;
+        (
+            Some((metadata.width, metadata.height)),
+            Some(metadata.duration_secs),
+        )
+    } else {
+        (read_image_dimensions(data), None)
+    };
+
+    if let Some((width, height)) = dimensions {
+        if limits.max_width.is_some_and(|max| width > max)
+            || limits.max_height.is_some_and(|max| height > max)
+        {
+            return Err(ImageHardenError::ResourceExhausted(format!(
+                "{}x{} exceeds --max-width/--max-height",
+                width, height
+            )));
+        }
+    }
+    if let (Some(duration_secs), Some(max)) = (duration_secs, limits.max_duration_secs) {
+        if duration_secs > max as f64 {
+            return Err(ImageHardenError::ResourceExhausted(format!(
+                "Duration {:.1}s exceeds --max-duration {}s",
+                duration_secs, max
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+enum ChildOutcome {
+    Success,
+    SandboxViolation,
+    Signaled(Signal),
+    Failed,
+}
+
+// The seccomp filters use ScmpAction::KillProcess, so a blocked syscall
+// terminates the child with SIGSYS rather than a normal exit status.
+//
+// Manual repro: add a syscall outside the allowlist to child_process (e.g.
+// std::fs::metadata("/") -> statx) and run the CLI on any PNG; the parent
+// reports a sandbox violation instead of a decode failure.
+fn classify_child_exit(status: WaitStatus) -> ChildOutcome {
+    match status {
+        WaitStatus::Exited(_, 0) => ChildOutcome::Success,
+        WaitStatus::Signaled(_, Signal::SIGSYS, _) => ChildOutcome::SandboxViolation,
+        WaitStatus::Signaled(_, signal, _) => ChildOutcome::Signaled(signal),
+        _ => ChildOutcome::Failed,
+    }
+}
+
+fn child_process(
+    image_path: &str,
+    file_extension: &str,
+    limits: &CliLimits,
+    write_pipe: &mut File,
+) -> isize {
     apply_landlock_rules(image_path).unwrap();
     let seccomp_filter = match file_extension {
         "svg" => apply_svg_seccomp_filter(),
@@ -94,7 +240,7 @@ fn child_process(image_path: &str, file_extension: &str, write_pipe: &mut File)
     };
     seccomp_filter.unwrap();
 
-    match decode_image(image_path) {
+    match decode_image(image_path, limits) {
         Ok(decoded_image_len) => {
             write_pipe
                 .write_all(decoded_image_len.to_string().as_bytes())
@@ -108,22 +254,38 @@ fn child_process(image_path: &str, file_extension: &str, write_pipe: &mut File)
     }
 }
 
-fn decode_image(image_path: &str) -> Result<usize, ImageHardenError> {
+fn decode_image(image_path: &str, limits: &CliLimits) -> Result<usize, ImageHardenError> {
     let path = Path::new(image_path);
-    let mut file = File::open(path)?;
+    let file = File::open(path)?;
     let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    // Read one byte past the cap instead of stat-ing the file (statx is not
+    // in the seccomp allowlist)
+    let max_file_size = limits.max_file_size.unwrap_or(usize::MAX);
+    file.take((max_file_size as u64).saturating_add(1))
+        .read_to_end(&mut buffer)?;
+    if buffer.len() > max_file_size {
+        return Err(ImageHardenError::ResourceExhausted(format!(
+            "File exceeds --max-file-size {} bytes",
+            max_file_size
+        )));
+    }
 
-    let result = match path.extension().and_then(|s| s.to_str()) {
+    let extension = path.extension().and_then(|s| s.to_str());
+    check_cli_limits(&buffer, extension == Some("mp4"), limits)?;
+
+    let result = match extension {
         Some("png") => decode_png(&buffer),
         Some("jpg") | Some("jpeg") => decode_jpeg(&buffer),
         Some("svg") => decode_svg(&buffer),
         Some("mp4") => {
-            let wasm_path = env::var("FFMPEG_WASM_PATH").unwrap_or_else(|_| "ffmpeg.wasm".to_string());
+            let wasm_path =
+                env::var("FFMPEG_WASM_PATH").unwrap_or_else(|_| "ffmpeg.wasm".to_string());
             decode_video(&buffer, &wasm_path)
         }
         _ => {
-            return Err(ImageHardenError::JpegError("Unsupported file type".to_string()));
+            return Err(ImageHardenError::JpegError(
+                "Unsupported file type".to_string(),
+            ));
         }
     };
 
@@ -207,18 +369,41 @@ fn perform_health_check() -> Result<(), String> {
     Ok(())
 }
 
+// Decode each embedded sample, print per-format results, return the exit code
+fn perform_self_test() -> i32 {
+    let results = run_self_test();
+    for result in &results {
+        match &result.result {
+            Ok(()) => println!("{}: ok", result.format),
+            Err(e) => println!("{}: FAILED: {}", result.format, e),
+        }
+    }
+    if results.iter().all(|result| result.passed()) {
+        0
+    } else {
+        1
+    }
+}
+
 fn print_help(program_name: &str) {
     println!("Image Harden CLI v{}", VERSION);
     println!("Hardened media file processing with memory safety and security sandboxing");
     println!();
     println!("USAGE:");
-    println!("    {} <FILE>", program_name);
+    println!("    {} [LIMIT OPTIONS] <FILE>", program_name);
     println!("    {} [OPTIONS]", program_name);
     println!();
     println!("OPTIONS:");
     println!("    -h, --help           Print this help message");
     println!("    -v, --version        Print version information");
     println!("    --health-check       Perform health check (for Kubernetes probes)");
+    println!("    --self-test          Decode built-in samples for each format; non-zero exit on failure");
+    println!();
+    println!("LIMIT OPTIONS:");
+    println!("    --max-width <PX>     Reject images/video wider than PX (PNG, JPEG, MP4)");
+    println!("    --max-height <PX>    Reject images/video taller than PX (PNG, JPEG, MP4)");
+    println!("    --max-file-size <B>  Reject input files larger than B bytes");
+    println!("    --max-duration <S>   Reject video longer than S seconds");
     println!();
     println!("SUPPORTED FORMATS:");
     println!("    Images:  PNG, JPEG, SVG");
@@ -236,5 +421,107 @@ fn print_help(program_name: &str) {
     println!("    {} image.png", program_name);
     println!("    {} audio.mp3", program_name);
     println!("    {} video.mp4", program_name);
+    println!("    {} --max-width 1024 image.png", program_name);
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::Pid;
+
+    #[test]
+    fn test_sigsys_is_sandbox_violation() {
+        let pid = Pid::from_raw(1234);
+        assert_eq!(
+            classify_child_exit(WaitStatus::Signaled(pid, Signal::SIGSYS, false)),
+            ChildOutcome::SandboxViolation
+        );
+        assert_eq!(
+            classify_child_exit(WaitStatus::Signaled(pid, Signal::SIGSEGV, true)),
+            ChildOutcome::Signaled(Signal::SIGSEGV)
+        );
+        assert_eq!(
+            classify_child_exit(WaitStatus::Exited(pid, 0)),
+            ChildOutcome::Success
+        );
+        assert_eq!(
+            classify_child_exit(WaitStatus::Exited(pid, 1)),
+            ChildOutcome::Failed
+        );
+    }
+
+    #[test]
+    fn test_self_test_exit_code() {
+        assert_eq!(perform_self_test(), 0);
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_limit_flags() {
+        let (path, limits) = parse_args(&args(&[
+            "--max-width",
+            "640",
+            "--max-height=480",
+            "--max-file-size",
+            "1000",
+            "--max-duration=30",
+            "image.png",
+        ]))
+        .unwrap();
+        assert_eq!(path, "image.png");
+        assert_eq!(
+            limits,
+            CliLimits {
+                max_width: Some(640),
+                max_height: Some(480),
+                max_file_size: Some(1000),
+                max_duration_secs: Some(30),
+            }
+        );
+
+        assert_eq!(
+            parse_args(&args(&["image.png"])).unwrap().1,
+            CliLimits::default()
+        );
+        for bad in [
+            &["--max-width", "0", "a.png"][..],
+            &["--max-width", "-5", "a.png"],
+            &["--max-height=big", "a.png"],
+            &["--max-duration"],
+            &["--max-depth", "3", "a.png"],
+            &["a.png", "b.png"],
+            &[],
+        ] {
+            assert!(parse_args(&args(bad)).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_tighter_max_width_rejects_valid_image() {
+        // 350x208 sample from the repository root
+        let path = env::temp_dir().join(format!("image_harden_cli_{}.png", std::process::id()));
+        std::fs::write(&path, include_bytes!("../../nurbcup2si.png")).unwrap();
+        let path_str = path.to_str().unwrap();
+
+        let default_decode = decode_image(path_str, &CliLimits::default());
+        let (_, tight) = parse_args(&args(&["--max-width", "100", path_str])).unwrap();
+        let tight_decode = decode_image(path_str, &tight);
+        let (_, small_file) = parse_args(&args(&["--max-file-size", "1024", path_str])).unwrap();
+        let small_file_decode = decode_image(path_str, &small_file);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(default_decode.is_ok());
+        assert!(matches!(
+            tight_decode,
+            Err(ImageHardenError::ResourceExhausted(_))
+        ));
+        assert!(matches!(
+            small_file_decode,
+            Err(ImageHardenError::ResourceExhausted(_))
+        ));
+    }
+}
@@ -3,9 +3,14 @@
 
 use lazy_static::lazy_static;
 use prometheus::{
-    Counter, CounterVec, Gauge, GaugeVec, Histogram, HistogramVec, Opts, Registry,
+    Counter, CounterVec, Encoder, Gauge, GaugeVec, Histogram, HistogramVec, Opts, Registry,
+    TextEncoder,
 };
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::TcpStream;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 lazy_static! {
     pub static ref REGISTRY: Registry = Registry::new();
@@ -72,6 +77,28 @@ lazy_static! {
         &["format"]
     ).unwrap();
 
+    pub static ref DECODED_BYTES_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("media_processor_decoded_bytes_total", "Total decoded output bytes")
+            .namespace("media_hardening"),
+        &["format"]
+    ).unwrap();
+
+    pub static ref DECODE_AMPLIFICATION_RATIO: HistogramVec = HistogramVec::new(
+        prometheus::HistogramOpts::new(
+            "media_processor_decode_amplification_ratio",
+            "Decoded output bytes per input byte (decompression bomb indicator)"
+        )
+        .namespace("media_hardening")
+        .buckets(vec![0.5, 1.0, 2.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0, 5000.0, 10000.0]),
+        &["format"]
+    ).unwrap();
+
+    pub static ref IN_FLIGHT_DECODES: GaugeVec = GaugeVec::new(
+        Opts::new("media_processor_in_flight_decodes", "Decodes currently running")
+            .namespace("media_hardening"),
+        &["format"]
+    ).unwrap();
+
     // Memory and CPU metrics
     pub static ref MEMORY_BYTES: Gauge = Gauge::new(
         "media_hardening_media_processor_memory_bytes",
@@ -148,6 +175,9 @@ pub fn init_metrics() -> Result<(), Box<dyn std::error::Error>> {
     REGISTRY.register(Box::new(RESOURCE_LIMIT_VIOLATIONS_TOTAL.clone()))?;
     REGISTRY.register(Box::new(PROCESSING_DURATION_SECONDS.clone()))?;
     REGISTRY.register(Box::new(FILE_SIZE_BYTES.clone()))?;
+    REGISTRY.register(Box::new(DECODED_BYTES_TOTAL.clone()))?;
+    REGISTRY.register(Box::new(DECODE_AMPLIFICATION_RATIO.clone()))?;
+    REGISTRY.register(Box::new(IN_FLIGHT_DECODES.clone()))?;
     REGISTRY.register(Box::new(MEMORY_BYTES.clone()))?;
     REGISTRY.register(Box::new(MEMORY_LIMIT_BYTES.clone()))?;
     REGISTRY.register(Box::new(CPU_SECONDS_TOTAL.clone()))?;
@@ -163,9 +193,11 @@ pub fn init_metrics() -> Result<(), Box<dyn std::error::Error>> {
     // Set initial values
     MEMORY_LIMIT_BYTES.set(2_000_000_000.0); // 2GB default
     KNOWN_CVES.set(0.0);
-    LAST_SECURITY_AUDIT_TIMESTAMP.set(std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs() as f64);
+    LAST_SECURITY_AUDIT_TIMESTAMP.set(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as f64,
+    );
 
     Ok(())
 }
@@ -183,14 +215,382 @@ pub fn record_file_processed(format: &str, file_size: usize, duration_secs: f64)
         .observe(duration_secs);
 }
 
+/// Record decoded output volume (compare with FILE_SIZE_BYTES for amplification)
+pub fn record_decoded_bytes(format: &str, output_len: usize) {
+    DECODED_BYTES_TOTAL
+        .with_label_values(&[format])
+        .inc_by(output_len as f64);
+}
+
+/// Record the output/input size ratio of a decode (skipped for empty input)
+pub fn record_decode_amplification(format: &str, input_len: usize, output_len: usize) {
+    if input_len == 0 {
+        return;
+    }
+    DECODE_AMPLIFICATION_RATIO
+        .with_label_values(&[format])
+        .observe(output_len as f64 / input_len as f64);
+}
+
+/// Tracks one in-flight decode in IN_FLIGHT_DECODES for its lifetime.
+/// The gauge is decremented on drop, so early returns and panics unwinding
+/// through the decode still leave it balanced.
+pub struct InFlightGuard {
+    gauge: Gauge,
+}
+
+impl InFlightGuard {
+    pub fn new(format: &str) -> Self {
+        let gauge = IN_FLIGHT_DECODES.with_label_values(&[format]);
+        gauge.inc();
+        Self { gauge }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+/// Self-contained metric set with its own registry.
+///
+/// The global statics above are shared by the whole process (and by every
+/// test in it). Embedders running several isolated decoder instances, or
+/// tests that assert exact counts, can own a `Metrics` instead. Metric
+/// names match the global ones so dashboards work unchanged.
+pub struct Metrics {
+    registry: Registry,
+    pub files_processed_total: CounterVec,
+    pub files_failed_total: CounterVec,
+    pub errors_total: CounterVec,
+    pub security_violations_total: CounterVec,
+    pub malformed_files_total: CounterVec,
+    pub processing_duration_seconds: HistogramVec,
+    pub file_size_bytes: HistogramVec,
+    pub decoded_bytes_total: CounterVec,
+    pub decode_amplification_ratio: HistogramVec,
+    pub in_flight_decodes: GaugeVec,
+}
+
+impl Metrics {
+    /// Create a fresh, independently registered metric set
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let metrics = Self {
+            registry: Registry::new(),
+            files_processed_total: CounterVec::new(
+                Opts::new(
+                    "media_processor_files_processed_total",
+                    "Total number of files processed",
+                )
+                .namespace("media_hardening"),
+                &["format", "status"],
+            )?,
+            files_failed_total: CounterVec::new(
+                Opts::new(
+                    "media_processor_files_failed_total",
+                    "Total number of files that failed processing",
+                )
+                .namespace("media_hardening"),
+                &["format", "error_type"],
+            )?,
+            errors_total: CounterVec::new(
+                Opts::new("media_processor_errors_total", "Total errors by type")
+                    .namespace("media_hardening"),
+                &["error_type"],
+            )?,
+            security_violations_total: CounterVec::new(
+                Opts::new(
+                    "media_processor_security_violations_total",
+                    "Total security violations detected",
+                )
+                .namespace("media_hardening"),
+                &["violation_type", "format"],
+            )?,
+            malformed_files_total: CounterVec::new(
+                Opts::new(
+                    "media_processor_malformed_files_total",
+                    "Total malformed files detected",
+                )
+                .namespace("media_hardening"),
+                &["format"],
+            )?,
+            processing_duration_seconds: HistogramVec::new(
+                prometheus::HistogramOpts::new(
+                    "media_processor_processing_duration_seconds",
+                    "Processing duration in seconds",
+                )
+                .namespace("media_hardening")
+                .buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0]),
+                &["format"],
+            )?,
+            file_size_bytes: HistogramVec::new(
+                prometheus::HistogramOpts::new(
+                    "media_processor_file_size_bytes",
+                    "File size distribution in bytes",
+                )
+                .namespace("media_hardening")
+                .buckets(vec![
+                    1024.0,
+                    10240.0,
+                    102400.0,
+                    1048576.0,
+                    10485760.0,
+                    104857600.0,
+                ]),
+                &["format"],
+            )?,
+            decoded_bytes_total: CounterVec::new(
+                Opts::new(
+                    "media_processor_decoded_bytes_total",
+                    "Total decoded output bytes",
+                )
+                .namespace("media_hardening"),
+                &["format"],
+            )?,
+            decode_amplification_ratio: HistogramVec::new(
+                prometheus::HistogramOpts::new(
+                    "media_processor_decode_amplification_ratio",
+                    "Decoded output bytes per input byte (decompression bomb indicator)",
+                )
+                .namespace("media_hardening")
+                .buckets(vec![
+                    0.5, 1.0, 2.0, 5.0, 10.0, 50.0, 100.0, 500.0, 1000.0, 5000.0, 10000.0,
+                ]),
+                &["format"],
+            )?,
+            in_flight_decodes: GaugeVec::new(
+                Opts::new(
+                    "media_processor_in_flight_decodes",
+                    "Decodes currently running",
+                )
+                .namespace("media_hardening"),
+                &["format"],
+            )?,
+        };
+
+        metrics
+            .registry
+            .register(Box::new(metrics.files_processed_total.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.files_failed_total.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.errors_total.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.security_violations_total.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.malformed_files_total.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.processing_duration_seconds.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.file_size_bytes.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.decoded_bytes_total.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.decode_amplification_ratio.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.in_flight_decodes.clone()))?;
+
+        Ok(metrics)
+    }
+
+    /// Registry holding only this instance's metrics
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Encode this instance's metrics in the Prometheus text format
+    pub fn gather_text(&self) -> Result<String, Box<dyn Error>> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+
+    pub fn record_file_processed(&self, format: &str, file_size: usize, duration_secs: f64) {
+        self.files_processed_total
+            .with_label_values(&[format, "success"])
+            .inc();
+        self.file_size_bytes
+            .with_label_values(&[format])
+            .observe(file_size as f64);
+        self.processing_duration_seconds
+            .with_label_values(&[format])
+            .observe(duration_secs);
+    }
+
+    pub fn record_file_failed(&self, format: &str, error_type: &str) {
+        self.files_failed_total
+            .with_label_values(&[format, error_type])
+            .inc();
+        self.errors_total.with_label_values(&[error_type]).inc();
+    }
+
+    pub fn record_decoded_bytes(&self, format: &str, output_len: usize) {
+        self.decoded_bytes_total
+            .with_label_values(&[format])
+            .inc_by(output_len as f64);
+    }
+
+    pub fn record_decode_amplification(&self, format: &str, input_len: usize, output_len: usize) {
+        if input_len == 0 {
+            return;
+        }
+        self.decode_amplification_ratio
+            .with_label_values(&[format])
+            .observe(output_len as f64 / input_len as f64);
+    }
+
+    pub fn record_security_violation(&self, violation_type: &str, format: &str) {
+        self.security_violations_total
+            .with_label_values(&[violation_type, format])
+            .inc();
+    }
+
+    pub fn record_malformed_file(&self, format: &str) {
+        self.malformed_files_total
+            .with_label_values(&[format])
+            .inc();
+    }
+}
+
+/// Flags decodes that run far slower than their input size predicts.
+///
+/// A lightweight complement to hard timeouts: inputs that stay under size
+/// limits but trigger algorithmic-complexity paths show up as
+/// `SUSPICIOUS_PATTERNS_TOTAL{pattern="slow_decode"}` without failing the
+/// decode. The budget is `expected_ns_per_byte * input_len * tolerance`,
+/// never less than `min_budget` so tiny inputs do not trip on scheduler noise.
+#[derive(Debug, Clone)]
+pub struct SlowDecodeDetector {
+    pub expected_ns_per_byte: f64,
+    pub tolerance: f64,
+    pub min_budget: Duration,
+}
+
+impl Default for SlowDecodeDetector {
+    fn default() -> Self {
+        Self {
+            expected_ns_per_byte: 100.0,
+            tolerance: 10.0,
+            min_budget: Duration::from_millis(50),
+        }
+    }
+}
+
+impl SlowDecodeDetector {
+    /// Time budget for an input of `input_len` bytes
+    pub fn budget(&self, input_len: usize) -> Duration {
+        let nanos = self.expected_ns_per_byte * input_len as f64 * self.tolerance;
+        Duration::from_secs_f64(nanos.max(0.0) / 1e9).max(self.min_budget)
+    }
+
+    /// True when `elapsed` exceeds the budget for `input_len` bytes
+    pub fn is_slow(&self, input_len: usize, elapsed: Duration) -> bool {
+        elapsed > self.budget(input_len)
+    }
+
+    /// Run `decode`, recording a slow_decode event if it overruns its budget.
+    /// Returns the decode result and whether it was flagged.
+    pub fn observe<T, F: FnOnce() -> T>(
+        &self,
+        format: &str,
+        input_len: usize,
+        decode: F,
+    ) -> (T, bool) {
+        let start = Instant::now();
+        let result = decode();
+        let slow = self.is_slow(input_len, start.elapsed());
+        if slow {
+            SUSPICIOUS_PATTERNS_TOTAL
+                .with_label_values(&["slow_decode", format])
+                .inc();
+        }
+        (result, slow)
+    }
+}
+
+/// Push all registered metrics to a Prometheus Pushgateway.
+///
+/// Intended for short-lived CLI/batch runs that exit before a scrape. Uses
+/// POST (merge semantics) to `<url>/metrics/job/<job>`. Only plain `http://`
+/// gateways are supported; non-2xx responses are returned as errors.
+pub fn push_to_gateway(url: &str, job: &str) -> Result<(), Box<dyn Error>> {
+    if job.is_empty()
+        || !job
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(format!("Invalid pushgateway job name: {:?}", job).into());
+    }
+
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or("Pushgateway URL must start with http://")?;
+    let (authority, prefix) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], rest[idx..].trim_end_matches('/')),
+        None => (rest, ""),
+    };
+    if authority.is_empty() {
+        return Err("Pushgateway URL has no host".into());
+    }
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&REGISTRY.gather(), &mut body)?;
+
+    let mut stream = TcpStream::connect(&address)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+
+    let header = format!(
+        "POST {}/metrics/job/{} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        prefix,
+        job,
+        authority,
+        prometheus::TEXT_FORMAT,
+        body.len()
+    );
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(&body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let status_line = response.split(|&b| b == b'\n').next().unwrap_or(&[]);
+    let status_line = String::from_utf8_lossy(status_line);
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format!("Malformed pushgateway response: {:?}", status_line.trim()))?;
+
+    if !(200..300).contains(&status) {
+        return Err(format!("Pushgateway returned HTTP {}", status).into());
+    }
+
+    Ok(())
+}
+
 /// Record a failed file processing
 pub fn record_file_failed(format: &str, error_type: &str) {
     FILES_FAILED_TOTAL
         .with_label_values(&[format, error_type])
         .inc();
-    ERRORS_TOTAL
-        .with_label_values(&[error_type])
-        .inc();
+    ERRORS_TOTAL.with_label_values(&[error_type]).inc();
 }
 
 /// Record a security violation
@@ -202,12 +602,171 @@ pub fn record_security_violation(violation_type: &str, format: &str) {
 
 /// Record a malformed file detection
 pub fn record_malformed_file(format: &str) {
-    MALFORMED_FILES_TOTAL
-        .with_label_values(&[format])
-        .inc();
+    MALFORMED_FILES_TOTAL.with_label_values(&[format]).inc();
 }
 
 /// Update memory usage gauge
 pub fn update_memory_usage(bytes: usize) {
     MEMORY_BYTES.set(bytes as f64);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amplification_ratio_observed() {
+        let histogram = DECODE_AMPLIFICATION_RATIO.with_label_values(&["test_amplification"]);
+        let (count, sum) = (histogram.get_sample_count(), histogram.get_sample_sum());
+
+        record_decode_amplification("test_amplification", 100, 25_000);
+        assert_eq!(histogram.get_sample_count(), count + 1);
+        assert!((histogram.get_sample_sum() - sum - 250.0).abs() < 1e-9);
+
+        // Empty input has no meaningful ratio
+        record_decode_amplification("test_amplification", 0, 25_000);
+        assert_eq!(histogram.get_sample_count(), count + 1);
+    }
+
+    #[test]
+    fn test_push_to_gateway_posts_prometheus_text() {
+        let _ = init_metrics();
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut request = server.recv().unwrap();
+            let method = request.method().to_string();
+            let url = request.url().to_string();
+            let mut body = String::new();
+            request.as_reader().read_to_string(&mut body).unwrap();
+            request.respond(tiny_http::Response::empty(202)).unwrap();
+            (method, url, body)
+        });
+
+        push_to_gateway(&format!("http://{}", addr), "imageharder_test").unwrap();
+        let (method, url, body) = handle.join().unwrap();
+
+        assert_eq!(method, "POST");
+        assert_eq!(url, "/metrics/job/imageharder_test");
+        assert!(body.contains("# TYPE media_hardening_media_processor_memory_limit_bytes gauge"));
+        for line in body
+            .lines()
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            let mut parts = line.rsplitn(2, ' ');
+            let value = parts.next().unwrap();
+            assert!(value.parse::<f64>().is_ok(), "bad sample line: {}", line);
+            assert!(parts.next().is_some(), "bad sample line: {}", line);
+        }
+    }
+
+    #[test]
+    fn test_push_to_gateway_reports_http_errors() {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        let handle = std::thread::spawn(move || {
+            let request = server.recv().unwrap();
+            request.respond(tiny_http::Response::empty(500)).unwrap();
+        });
+
+        let result = push_to_gateway(&format!("http://{}/prefix/", addr), "job");
+        handle.join().unwrap();
+        assert!(result.is_err());
+
+        assert!(push_to_gateway("https://gateway:9091", "job").is_err());
+        assert!(push_to_gateway("http://gateway:9091", "bad/job").is_err());
+    }
+
+    #[test]
+    fn test_metrics_instances_are_independent() {
+        let first = Metrics::new().unwrap();
+        let second = Metrics::new().unwrap();
+
+        first.record_file_processed("png", 1024, 0.01);
+        first.record_file_processed("png", 2048, 0.02);
+        first.record_decoded_bytes("png", 4096);
+        second.record_file_failed("png", "png_error");
+
+        let processed_isolated = |counter: &CounterVec| {
+            counter
+                .with_label_values(&["png_isolated", "success"])
+                .get()
+        };
+        let processed = |m: &Metrics| {
+            m.files_processed_total
+                .with_label_values(&["png", "success"])
+                .get()
+        };
+        assert_eq!(processed(&first), 2.0);
+        assert_eq!(processed(&second), 0.0);
+        assert_eq!(
+            first.decoded_bytes_total.with_label_values(&["png"]).get(),
+            4096.0
+        );
+        assert_eq!(
+            second.decoded_bytes_total.with_label_values(&["png"]).get(),
+            0.0
+        );
+        assert_eq!(
+            second.errors_total.with_label_values(&["png_error"]).get(),
+            1.0
+        );
+
+        // Same metric names in both registries; the global registry is untouched
+        let text = second.gather_text().unwrap();
+        assert!(text.contains("media_hardening_media_processor_files_failed_total"));
+        assert!(!text.contains("media_hardening_media_processor_files_processed_total{"));
+        first.record_file_processed("png_isolated", 512, 0.01);
+        assert_eq!(processed_isolated(&first.files_processed_total), 1.0);
+        assert_eq!(processed_isolated(&FILES_PROCESSED_TOTAL), 0.0);
+    }
+
+    #[test]
+    fn test_slow_decode_flagged_without_failing() {
+        let detector = SlowDecodeDetector {
+            expected_ns_per_byte: 1000.0,
+            tolerance: 2.0,
+            min_budget: Duration::from_millis(1),
+        };
+        let counter = SUSPICIOUS_PATTERNS_TOTAL.with_label_values(&["slow_decode", "test_slow"]);
+        let before = counter.get();
+
+        // 16 bytes -> 32us budget, floored to 1ms; the synthetic decode takes far longer
+        let (result, slow) = detector.observe("test_slow", 16, || {
+            std::thread::sleep(Duration::from_millis(30));
+            Ok::<_, ()>(vec![0u8; 4])
+        });
+        assert!(slow);
+        assert_eq!(result.unwrap().len(), 4);
+        assert_eq!(counter.get(), before + 1.0);
+
+        // 1 MB of input at 1000ns/byte x2 allows 2s
+        let (_, slow) = detector.observe("test_slow", 1 << 20, || ());
+        assert!(!slow);
+        assert_eq!(counter.get(), before + 1.0);
+
+        assert_eq!(
+            SlowDecodeDetector::default().budget(0),
+            Duration::from_millis(50)
+        );
+    }
+
+    #[test]
+    fn test_in_flight_guard_returns_to_zero() {
+        let gauge = IN_FLIGHT_DECODES.with_label_values(&["test_in_flight"]);
+        {
+            let _outer = InFlightGuard::new("test_in_flight");
+            let _inner = InFlightGuard::new("test_in_flight");
+            assert_eq!(gauge.get(), 2.0);
+        }
+        assert_eq!(gauge.get(), 0.0);
+
+        let result = std::panic::catch_unwind(|| {
+            let _guard = InFlightGuard::new("test_in_flight");
+            panic!("decoder panicked");
+        });
+        assert!(result.is_err());
+        assert_eq!(gauge.get(), 0.0);
+    }
+}
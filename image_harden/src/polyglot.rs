@@ -0,0 +1,408 @@
+///! Polyglot / embedded-payload scanner for raw file bytes
+///!
+///! Decoders stop at the end of the primary image and silently ignore the
+///! rest, which is what GIFAR-style and image+archive polyglots rely on.
+///! This scanner walks the primary container (PNG, JPEG, GIF) to find where
+///! the image really ends, then reports any secondary file signatures.
+///!
+///! Security measures:
+///! - Bounds-checked, overflow-safe container walks
+///! - PE detection requires a valid `PE\0\0` header (bare `MZ` is too common)
+///! - Never decodes or executes the embedded payloads
+
+/// Secondary file types recognized inside a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddedKind {
+    /// ZIP local file header or end-of-central-directory (also JAR/DOCX/APK)
+    Zip,
+    /// PDF header
+    Pdf,
+    /// ELF executable
+    Elf,
+    /// Windows PE executable (MZ stub with valid PE header)
+    PortableExecutable,
+    /// Script interpreter line (`#!/`)
+    Script,
+}
+
+/// A secondary signature found in the buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedSignature {
+    pub offset: usize,
+    pub kind: EmbeddedKind,
+    /// True when the signature lies after the end of the primary image
+    pub beyond_primary_end: bool,
+}
+
+/// Result of a polyglot scan
+#[derive(Debug, Clone, Default)]
+pub struct PolyglotReport {
+    /// End offset of the primary image, when its container could be walked
+    pub primary_end: Option<usize>,
+    /// Bytes following the primary image
+    pub trailing_bytes: usize,
+    pub embedded: Vec<EmbeddedSignature>,
+}
+
+impl PolyglotReport {
+    /// True when any secondary file signature was found
+    pub fn is_polyglot(&self) -> bool {
+        !self.embedded.is_empty()
+    }
+}
+
+/// Scan raw bytes for embedded secondary file signatures
+///
+/// Offset 0 is skipped since it holds the primary file's own signature.
+pub fn scan_polyglot(data: &[u8]) -> PolyglotReport {
+    let primary_end = primary_image_end(data);
+    let trailing_bytes = primary_end.map_or(0, |end| data.len() - end);
+
+    let mut embedded = Vec::new();
+    for offset in 1..data.len() {
+        if let Some(kind) = signature_at(data, offset) {
+            embedded.push(EmbeddedSignature {
+                offset,
+                kind,
+                beyond_primary_end: primary_end.is_some_and(|end| offset >= end),
+            });
+        }
+    }
+
+    PolyglotReport {
+        primary_end,
+        trailing_bytes,
+        embedded,
+    }
+}
+
+/// Find a second PNG, JPEG or GIF image appended after the primary one
+///
+/// Returns the offset and format name of the first image signature beyond
+/// the primary image's end. Signatures inside the primary image (e.g. an
+/// EXIF thumbnail) are not reported.
+pub fn find_concatenated_image(data: &[u8]) -> Option<(usize, &'static str)> {
+    let end = primary_image_end(data)?;
+    (end..data.len())
+        .find_map(|offset| image_signature_at(&data[offset..]).map(|name| (offset, name)))
+}
+
+fn image_signature_at(rest: &[u8]) -> Option<&'static str> {
+    if rest.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("PNG")
+    } else if rest.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("JPEG")
+    } else if rest.starts_with(b"GIF87a") || rest.starts_with(b"GIF89a") {
+        Some("GIF")
+    } else {
+        None
+    }
+}
+
+fn signature_at(data: &[u8], offset: usize) -> Option<EmbeddedKind> {
+    let rest = &data[offset..];
+    if rest.starts_with(b"PK\x03\x04") || rest.starts_with(b"PK\x05\x06") {
+        Some(EmbeddedKind::Zip)
+    } else if rest.starts_with(b"%PDF-") {
+        Some(EmbeddedKind::Pdf)
+    } else if rest.starts_with(b"\x7fELF") {
+        Some(EmbeddedKind::Elf)
+    } else if rest.starts_with(b"#!/") {
+        Some(EmbeddedKind::Script)
+    } else if rest.starts_with(b"MZ") && has_pe_header(rest) {
+        Some(EmbeddedKind::PortableExecutable)
+    } else {
+        None
+    }
+}
+
+/// Validate the DOS header's `e_lfanew` pointer to a `PE\0\0` signature
+fn has_pe_header(mz: &[u8]) -> bool {
+    if mz.len() < 0x40 {
+        return false;
+    }
+    let e_lfanew = u32::from_le_bytes([mz[0x3C], mz[0x3D], mz[0x3E], mz[0x3F]]) as usize;
+    match e_lfanew.checked_add(4) {
+        Some(end) if end <= mz.len() => &mz[e_lfanew..end] == b"PE\0\0",
+        _ => false,
+    }
+}
+
+/// Offset just past the primary image, for the formats we can walk
+pub(crate) fn primary_image_end(data: &[u8]) -> Option<usize> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_end(data)
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_end(data)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        gif_end(data)
+    } else {
+        None
+    }
+}
+
+/// Walk PNG chunks up to and including IEND
+fn png_end(data: &[u8]) -> Option<usize> {
+    let mut pos = 8usize;
+    loop {
+        let header = data.get(pos..pos.checked_add(8)?)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        // length + type + data + crc
+        let next = pos.checked_add(12)?.checked_add(length)?;
+        if next > data.len() {
+            return None;
+        }
+        if &header[4..8] == b"IEND" {
+            return Some(next);
+        }
+        pos = next;
+    }
+}
+
+/// Walk JPEG marker segments and entropy-coded data up to EOI
+fn jpeg_end(data: &[u8]) -> Option<usize> {
+    let mut pos = 2usize;
+    loop {
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            // Fill bytes before a marker
+            0xFF => pos += 1,
+            0xD9 => return Some(pos + 2),
+            // Standalone markers without a length field
+            0x01 | 0xD0..=0xD7 => pos += 2,
+            _ => {
+                let len_bytes = data.get(pos + 2..pos + 4)?;
+                let length = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+                if length < 2 {
+                    return None;
+                }
+                pos = pos.checked_add(2)?.checked_add(length)?;
+                if pos > data.len() {
+                    return None;
+                }
+
+                if marker == 0xDA {
+                    // Skip entropy-coded data: stop at a marker that is not a
+                    // stuffed zero byte or a restart marker
+                    loop {
+                        let byte = *data.get(pos)?;
+                        if byte == 0xFF {
+                            let next = *data.get(pos + 1)?;
+                            if next != 0x00 && !(0xD0..=0xD7).contains(&next) {
+                                break;
+                            }
+                            pos += 2;
+                        } else {
+                            pos += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// True when a PNG chunk of type `kind` appears before IEND
+pub(crate) fn png_has_chunk(data: &[u8], kind: &[u8; 4]) -> bool {
+    png_chunk_data(data, kind).is_some()
+}
+
+/// Payload of the first PNG chunk of type `kind` before IEND, truncated to
+/// the bytes actually present
+pub(crate) fn png_chunk_data<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut pos = 8usize;
+    while let Some(header) = data.get(pos..pos.saturating_add(8)) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        if &header[4..8] == kind {
+            let start = pos + 8;
+            return Some(&data[start..start.saturating_add(length).min(data.len())]);
+        }
+        if &header[4..8] == b"IEND" {
+            break;
+        }
+        pos = match pos.checked_add(12).and_then(|p| p.checked_add(length)) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    None
+}
+
+/// Walk GIF blocks up to and including the trailer
+fn gif_end(data: &[u8]) -> Option<usize> {
+    walk_gif(data, &mut |_| {})
+}
+
+/// Image descriptors in a GIF, counted up to the trailer or the first
+/// malformed block
+pub(crate) fn gif_frame_count(data: &[u8]) -> usize {
+    let mut frames = 0;
+    walk_gif(data, &mut |_| frames += 1);
+    frames
+}
+
+/// Image descriptors carrying a local color table, counted the same way
+pub(crate) fn gif_local_color_table_count(data: &[u8]) -> usize {
+    let mut tables = 0;
+    walk_gif(data, &mut |flags| {
+        if flags & 0x80 != 0 {
+            tables += 1;
+        }
+    });
+    tables
+}
+
+/// Walk GIF blocks, passing each image descriptor's packed flags to
+/// `on_descriptor`
+fn walk_gif(data: &[u8], on_descriptor: &mut dyn FnMut(u8)) -> Option<usize> {
+    let mut pos = 13usize; // header + logical screen descriptor
+    let flags = *data.get(10)?;
+    if flags & 0x80 != 0 {
+        pos += 3 * (1usize << ((flags & 0x07) + 1));
+    }
+
+    loop {
+        match *data.get(pos)? {
+            0x3B => return Some(pos + 1),
+            0x21 => {
+                // Extension: introducer, label, sub-blocks
+                pos = skip_gif_sub_blocks(data, pos + 2)?;
+            }
+            0x2C => {
+                // Image descriptor, optional local color table, LZW code size
+                let flags = *data.get(pos + 9)?;
+                on_descriptor(flags);
+                pos += 10;
+                if flags & 0x80 != 0 {
+                    pos += 3 * (1usize << ((flags & 0x07) + 1));
+                }
+                pos = skip_gif_sub_blocks(data, pos + 1)?;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Skip a chain of GIF data sub-blocks, returning the offset after the terminator
+pub(crate) fn skip_gif_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let size = *data.get(pos)? as usize;
+        pos = pos.checked_add(1 + size)?;
+        if size == 0 {
+            return Some(pos);
+        }
+        if pos > data.len() {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_clean_png_has_no_findings() {
+        let png = test_support::png_rgba(4, 4, [1, 2, 3, 255]);
+        let report = scan_polyglot(&png);
+        assert_eq!(report.primary_end, Some(png.len()));
+        assert_eq!(report.trailing_bytes, 0);
+        assert!(!report.is_polyglot());
+    }
+
+    #[test]
+    fn test_png_zip_polyglot() {
+        let mut data = test_support::png_rgba(4, 4, [1, 2, 3, 255]);
+        let png_len = data.len();
+        data.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00\x08\x00payload.jar");
+        data.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00");
+
+        let report = scan_polyglot(&data);
+        assert_eq!(report.primary_end, Some(png_len));
+        assert_eq!(report.trailing_bytes, data.len() - png_len);
+        assert_eq!(report.embedded.len(), 2);
+        assert_eq!(report.embedded[0].offset, png_len);
+        assert_eq!(report.embedded[0].kind, EmbeddedKind::Zip);
+        assert!(report.embedded.iter().all(|e| e.beyond_primary_end));
+    }
+
+    #[test]
+    fn test_detects_elf_pdf_and_script() {
+        let mut data = test_support::png_rgba(1, 1, [0, 0, 0, 0]);
+        data.extend_from_slice(b"\x7fELF\x02\x01\x01");
+        data.extend_from_slice(b"%PDF-1.7\n");
+        data.extend_from_slice(b"#!/bin/sh\n");
+        let kinds: Vec<_> = scan_polyglot(&data)
+            .embedded
+            .iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![EmbeddedKind::Elf, EmbeddedKind::Pdf, EmbeddedKind::Script]
+        );
+    }
+
+    #[test]
+    fn test_bare_mz_is_ignored_but_pe_is_detected() {
+        let mut data = test_support::png_rgba(1, 1, [0, 0, 0, 0]);
+        data.extend_from_slice(b"MZ not an executable");
+        assert!(!scan_polyglot(&data).is_polyglot());
+
+        let pe_offset = data.len();
+        let mut pe = vec![0u8; 0x80];
+        pe[0..2].copy_from_slice(b"MZ");
+        pe[0x3C..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        data.extend_from_slice(&pe);
+
+        let report = scan_polyglot(&data);
+        assert_eq!(report.embedded.len(), 1);
+        assert_eq!(report.embedded[0].offset, pe_offset);
+        assert_eq!(report.embedded[0].kind, EmbeddedKind::PortableExecutable);
+    }
+
+    #[test]
+    fn test_gif_and_jpeg_ends() {
+        // Minimal GIF: header, 1x1 screen, no color table, trailer
+        let gif = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;".to_vec();
+        assert_eq!(primary_image_end(&gif), Some(gif.len()));
+
+        // SOI, APP0 stub, SOS with entropy data containing stuffing and RST, EOI
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xDA, 0x00, 0x02, 0x12, 0xFF,
+            0x00, 0x34, 0xFF, 0xD0, 0x56, 0xFF, 0xD9,
+        ];
+        assert_eq!(primary_image_end(&jpeg), Some(jpeg.len()));
+    }
+
+    #[test]
+    fn test_concatenated_images() {
+        let mut pngs = test_support::png_rgba(2, 2, [1, 2, 3, 255]);
+        let first_len = pngs.len();
+        assert_eq!(find_concatenated_image(&pngs), None);
+        pngs.extend_from_slice(&test_support::png_rgba(64, 64, [0, 0, 0, 0]));
+        assert_eq!(find_concatenated_image(&pngs), Some((first_len, "PNG")));
+
+        let mut jpegs = test_support::JPEG_RGB_8X8.to_vec();
+        assert_eq!(find_concatenated_image(&jpegs), None);
+        jpegs.extend_from_slice(b"padding");
+        jpegs.extend_from_slice(&test_support::JPEG_RGB_8X8);
+        assert_eq!(
+            find_concatenated_image(&jpegs),
+            Some((test_support::JPEG_RGB_8X8.len() + 7, "JPEG"))
+        );
+    }
+
+    #[test]
+    fn test_truncated_container_has_unknown_end() {
+        let png = test_support::png_rgba(2, 2, [9, 9, 9, 9]);
+        let report = scan_polyglot(&png[..png.len() - 6]);
+        assert_eq!(report.primary_end, None);
+        assert_eq!(report.trailing_bytes, 0);
+    }
+}
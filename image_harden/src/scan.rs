@@ -0,0 +1,178 @@
+///! One-call file scan aggregating the hardening checks into a verdict
+///!
+///! `scan` sniffs the format, reads header dimensions, runs the hardened
+///! decoder, and adds the raw-byte checks (polyglot signatures, trailing
+///! data, concatenated images, metadata elements, CVE risk hints). Each
+///! check reports `Finding`s; the report's verdict is the worst of them.
+///!
+///! Security measures:
+///! - Same decoder and limits as `HardenedDecoder`, except that trailing
+///!   data and concatenated images are reported as findings, not errors
+///! - Raw-byte checks never decode or execute embedded payloads
+///! - CVE risk hints are informational only, as `cve_risk_hints` requires
+use crate::api::{detect_format, DecoderOptions, HardenedDecoder, Limits, MediaFormat};
+use crate::polyglot::{find_concatenated_image, scan_polyglot};
+use crate::structure::walk_structure;
+use crate::{cve_risk_hints, read_image_dimensions};
+
+/// PNG chunks and JPEG segments that carry metadata rather than pixels
+const METADATA_ELEMENTS: &[&str] = &[
+    "tEXt", "zTXt", "iTXt", "eXIf", "iCCP", "tIME", "APP1", "APP2", "APP13", "COM",
+];
+
+/// Scan outcome, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+    /// Decodes under the hardened limits with nothing unusual found
+    Clean,
+    /// Decodes, but carries data a viewer or parser could be tricked by
+    Suspicious,
+    /// Unrecognized, or refused by the hardened decoder
+    Rejected,
+}
+
+/// One observation from a check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Short stable check name (e.g. "decode", "polyglot", "metadata")
+    pub check: &'static str,
+    pub detail: String,
+    /// Verdict this finding alone would give; Clean for informational ones
+    pub verdict: Verdict,
+}
+
+/// Result of `scan`
+#[derive(Debug, Clone)]
+pub struct ScanReport {
+    /// Format sniffed from magic bytes
+    pub format: Option<MediaFormat>,
+    /// Header width and height, for formats `read_image_dimensions` knows
+    pub dimensions: Option<(u32, u32)>,
+    pub findings: Vec<Finding>,
+    /// Worst verdict among the findings, Clean when there are none
+    pub verdict: Verdict,
+}
+
+/// Run every applicable check on `data` and summarize them in a verdict
+pub fn scan(data: &[u8]) -> ScanReport {
+    let mut findings = Vec::new();
+    let mut add = |check, detail: String, verdict| {
+        findings.push(Finding {
+            check,
+            detail,
+            verdict,
+        })
+    };
+
+    let format = detect_format(data);
+    let dimensions = read_image_dimensions(data);
+    match format {
+        Some(format) => {
+            let options = DecoderOptions::default().with_limits(Limits {
+                reject_concatenated_images: false,
+                reject_trailing_data: false,
+                ..Limits::balanced()
+            });
+            if let Err(e) = HardenedDecoder::decode_with_options(format, data, &options) {
+                add("decode", e.to_string(), Verdict::Rejected);
+            }
+
+            walk_structure(format, data, |event| {
+                if METADATA_ELEMENTS.contains(&event.kind) {
+                    add(
+                        "metadata",
+                        format!("{} at offset {}", event.kind, event.offset),
+                        Verdict::Clean,
+                    );
+                }
+            });
+            for hint in cve_risk_hints(format, data) {
+                add("cve_risk_hint", hint.to_string(), Verdict::Clean);
+            }
+        }
+        None => add(
+            "format",
+            "No recognized file signature".to_string(),
+            Verdict::Rejected,
+        ),
+    }
+
+    let polyglot = scan_polyglot(data);
+    for embedded in &polyglot.embedded {
+        add(
+            "polyglot",
+            format!(
+                "{:?} signature at offset {}",
+                embedded.kind, embedded.offset
+            ),
+            Verdict::Suspicious,
+        );
+    }
+    if polyglot.trailing_bytes > 0 {
+        add(
+            "trailing_data",
+            format!("{} bytes after the primary image", polyglot.trailing_bytes),
+            Verdict::Suspicious,
+        );
+    }
+    if let Some((offset, kind)) = find_concatenated_image(data) {
+        add(
+            "concatenated_image",
+            format!("{} image at offset {}", kind, offset),
+            Verdict::Suspicious,
+        );
+    }
+
+    let verdict = findings
+        .iter()
+        .map(|finding| finding.verdict)
+        .max()
+        .unwrap_or(Verdict::Clean);
+    ScanReport {
+        format,
+        dimensions,
+        findings,
+        verdict,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_clean_png_scans_clean() {
+        let report = scan(&test_support::png_rgba(4, 3, [1, 2, 3, 255]));
+        assert_eq!(report.format, Some(MediaFormat::Png));
+        assert_eq!(report.dimensions, Some((4, 3)));
+        assert!(report.findings.is_empty());
+        assert_eq!(report.verdict, Verdict::Clean);
+    }
+
+    #[test]
+    fn test_polyglot_scans_suspicious() {
+        let mut data = test_support::png_rgba(4, 4, [1, 2, 3, 255]);
+        data.extend_from_slice(b"PK\x03\x04\x14\x00\x00\x00\x08\x00payload.jar");
+        let report = scan(&data);
+        assert_eq!(report.verdict, Verdict::Suspicious);
+        let checks: Vec<_> = report.findings.iter().map(|f| f.check).collect();
+        assert_eq!(checks, ["polyglot", "trailing_data"]);
+
+        // Metadata alone is informational
+        let text = test_support::png_chunk(b"tEXt", b"k\0v");
+        let report = scan(&test_support::png(1, 1, 8, 0, &[7], &[text]));
+        assert_eq!(report.findings[0].check, "metadata");
+        assert_eq!(report.verdict, Verdict::Clean);
+    }
+
+    #[test]
+    fn test_unrecognized_or_undecodable_is_rejected() {
+        assert_eq!(scan(b"not an image").verdict, Verdict::Rejected);
+        let png = test_support::png_rgba(4, 4, [1, 2, 3, 255]);
+        let report = scan(&png[..png.len() - 20]);
+        assert_eq!(report.format, Some(MediaFormat::Png));
+        assert_eq!(report.verdict, Verdict::Rejected);
+        assert_eq!(report.findings[0].check, "decode");
+    }
+}
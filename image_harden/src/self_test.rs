@@ -0,0 +1,148 @@
+///! Built-in decoder self-test against embedded known-good samples
+///!
+///! A health check that only allocates memory passes even when a codec
+///! library failed to link or load. This decodes a tiny sample per compiled-in
+///! format through the public API and checks the output size, so a broken
+///! libpng, libjpeg, giflib, or libwebp build is caught at startup.
+use crate::api::{DecodedMedia, HardenedDecoder, MediaFormat};
+use crate::{decode_png_full, ImageHardenError};
+
+// 2x2 RGBA PNG, solid (10, 20, 30, 255)
+const SAMPLE_PNG: [u8; 86] = [
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x02, 0x08, 0x06, 0x00, 0x00, 0x00, 0x72, 0xB6, 0x0D,
+    0x24, 0x00, 0x00, 0x00, 0x1D, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x01, 0x12, 0x00, 0xED, 0xFF,
+    0x00, 0x0A, 0x14, 0x1E, 0xFF, 0x0A, 0x14, 0x1E, 0xFF, 0x00, 0x0A, 0x14, 0x1E, 0xFF, 0x0A, 0x14,
+    0x1E, 0xFF, 0x26, 0x8C, 0x04, 0xED, 0xAC, 0xAE, 0x14, 0xE8, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+    0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+];
+
+// 8x8 single-component baseline JPEG (libjpeg q90), solid gray 128
+pub(crate) const SAMPLE_JPEG: [u8; 159] = [
+    0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0x00, 0x00, 0x01,
+    0x00, 0x01, 0x00, 0x00, 0xFF, 0xDB, 0x00, 0x43, 0x00, 0x03, 0x02, 0x02, 0x03, 0x02, 0x02, 0x03,
+    0x03, 0x03, 0x03, 0x04, 0x03, 0x03, 0x04, 0x05, 0x08, 0x05, 0x05, 0x04, 0x04, 0x05, 0x0A, 0x07,
+    0x07, 0x06, 0x08, 0x0C, 0x0A, 0x0C, 0x0C, 0x0B, 0x0A, 0x0B, 0x0B, 0x0D, 0x0E, 0x12, 0x10, 0x0D,
+    0x0E, 0x11, 0x0E, 0x0B, 0x0B, 0x10, 0x16, 0x10, 0x11, 0x13, 0x14, 0x15, 0x15, 0x15, 0x0C, 0x0F,
+    0x17, 0x18, 0x16, 0x14, 0x18, 0x12, 0x14, 0x15, 0x14, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08,
+    0x00, 0x08, 0x01, 0x01, 0x11, 0x00, 0xFF, 0xC4, 0x00, 0x14, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xC4, 0x00, 0x14,
+    0x10, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00, 0x3F, 0xFF, 0xD9,
+];
+
+// 2x2 GIF89a, two-color palette in a checkerboard
+const SAMPLE_GIF: [u8; 36] = [
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x02, 0x00, 0x02, 0x00, 0xF0, 0x00, 0x00, 0x0A, 0x14, 0x1E,
+    0xC8, 0x64, 0x32, 0x2C, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x02, 0x03, 0x44,
+    0x18, 0x14, 0x00, 0x3B,
+];
+
+// 8x8 lossy WebP without alpha (libwebp q90), RGB (200, 100, 50)
+pub(crate) const SAMPLE_WEBP: [u8; 70] = [
+    0x52, 0x49, 0x46, 0x46, 0x3E, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38, 0x20,
+    0x32, 0x00, 0x00, 0x00, 0xD0, 0x01, 0x00, 0x9D, 0x01, 0x2A, 0x08, 0x00, 0x08, 0x00, 0x00, 0xC0,
+    0x12, 0x25, 0xA0, 0x02, 0x74, 0xBA, 0x01, 0xF8, 0x00, 0x03, 0xB0, 0x00, 0xFE, 0xDA, 0x26, 0xFF,
+    0xEE, 0xF3, 0x7E, 0xD3, 0xD7, 0xB4, 0xF5, 0xFD, 0x4C, 0xFF, 0xF8, 0xCA, 0x9F, 0x20, 0x3F, 0xE3,
+    0x2A, 0x7F, 0xC5, 0xCC, 0x00, 0x00,
+];
+
+// 16-sample mono 16-bit FLAC, verbatim subframe
+const SAMPLE_FLAC: [u8; 84] = [
+    0x66, 0x4C, 0x61, 0x43, 0x80, 0x00, 0x00, 0x22, 0x00, 0x10, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x01, 0xF4, 0x00, 0xF0, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xF8, 0x60, 0x08, 0x00, 0x0F,
+    0x96, 0x02, 0xFC, 0xE0, 0xFD, 0x44, 0xFD, 0xA8, 0xFE, 0x0C, 0xFE, 0x70, 0xFE, 0xD4, 0xFF, 0x38,
+    0xFF, 0x9C, 0x00, 0x00, 0x00, 0x64, 0x00, 0xC8, 0x01, 0x2C, 0x01, 0x90, 0x01, 0xF4, 0x02, 0x58,
+    0x02, 0xBC, 0xC5, 0x34,
+];
+
+// 4x4 SVG, one solid (10, 20, 30) rect
+const SAMPLE_SVG: &[u8] = b"<svg xmlns='http://www.w3.org/2000/svg' width='4' height='4'>\
+    <rect width='4' height='4' fill='#0a141e'/></svg>";
+
+/// Outcome of decoding one format's embedded sample
+#[derive(Debug)]
+pub struct SelfTestResult {
+    pub format: &'static str,
+    pub result: Result<(), ImageHardenError>,
+}
+
+impl SelfTestResult {
+    pub fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Decode every embedded sample and report per-format pass/fail
+pub fn run_self_test() -> Vec<SelfTestResult> {
+    // (format, sample, expected output size in bytes)
+    let samples: [(MediaFormat, &[u8], usize); 6] = [
+        (MediaFormat::Png, &SAMPLE_PNG, 2 * 2 * 4),
+        (MediaFormat::Jpeg, &SAMPLE_JPEG, 8 * 8 * 3),
+        (MediaFormat::Gif, &SAMPLE_GIF, 2 * 2 * 4),
+        (MediaFormat::WebP, &SAMPLE_WEBP, 8 * 8 * 3),
+        // SVG renders a 256x256 RGBA pixmap, returned as PNG
+        (MediaFormat::Svg, SAMPLE_SVG, 256 * 256 * 4),
+        (MediaFormat::AudioFlac, &SAMPLE_FLAC, 16 * 2),
+    ];
+
+    samples
+        .iter()
+        .map(|&(format, sample, expected_len)| SelfTestResult {
+            format: format.name(),
+            result: check_sample(format, sample, expected_len),
+        })
+        .collect()
+}
+
+fn check_sample(
+    format: MediaFormat,
+    sample: &[u8],
+    expected_len: usize,
+) -> Result<(), ImageHardenError> {
+    let decoded_len = match (format, HardenedDecoder::decode(format, sample)?) {
+        (MediaFormat::Svg, DecodedMedia::Image(png)) => decode_png_full(&png)?.data.len(),
+        (_, decoded) => decoded.byte_len(),
+    };
+    if decoded_len != expected_len {
+        return Err(ImageHardenError::ValidationFailure(format!(
+            "{} sample decoded to {} bytes, expected {}",
+            format.name(),
+            decoded_len,
+            expected_len
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_samples_match_test_fixtures() {
+        assert_eq!(
+            SAMPLE_PNG[..],
+            test_support::png_rgba(2, 2, [10, 20, 30, 255])[..]
+        );
+        let checkerboard = test_support::TestGifFrame::new(0, 0, 2, 2, vec![0, 1, 1, 0]);
+        let gif = test_support::gif(2, 2, &[[10, 20, 30], [200, 100, 50]], &[checkerboard]);
+        assert_eq!(SAMPLE_GIF[..], gif[..]);
+        let ramp: Vec<i32> = (-8..8).map(|i| i * 100).collect();
+        assert_eq!(
+            SAMPLE_FLAC[..],
+            test_support::flac_mono(&ramp, 16, 8000)[..]
+        );
+    }
+
+    #[test]
+    fn test_self_test_passes() {
+        let results = run_self_test();
+        assert_eq!(results.len(), 6);
+        for result in &results {
+            assert!(result.passed(), "{}: {:?}", result.format, result.result);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+///! LSB steganography heuristics for decoded images
+///!
+///! Implements the Westfeld-Pfitzmann chi-square attack plus an LSB-plane
+///! entropy measure over the color samples of a `DecodedImage`.
+///!
+///! This is a triage heuristic, NOT a guarantee:
+///! - Sparse-payload or adaptive embedding can evade it entirely
+///! - Synthetic images with flat histograms can produce false positives
+///! - Only spatial-domain LSB replacement is modeled (not JPEG DCT stego)
+use crate::DecodedImage;
+
+/// Embedding probability above which an image is flagged
+const SUSPICIOUS_THRESHOLD: f64 = 0.95;
+
+/// Minimum number of color samples for a meaningful statistic
+const MIN_SAMPLES: usize = 1024;
+
+/// Result of the LSB anomaly analysis
+#[derive(Debug, Clone)]
+pub struct StegoScore {
+    /// Chi-square statistic over pairs of values (PoVs)
+    pub chi_square: f64,
+    /// Probability of LSB embedding derived from the chi-square test (0.0-1.0)
+    pub embedding_probability: f64,
+    /// Shannon entropy of the LSB plane in bits (1.0 = perfectly random)
+    pub lsb_entropy: f64,
+    /// Number of color samples analyzed
+    pub samples_analyzed: usize,
+    /// True when the image looks like it carries LSB-embedded data
+    pub suspicious: bool,
+}
+
+/// Score a decoded image for LSB-replacement steganography
+///
+/// Alpha channels (2- and 4-channel images) are excluded from analysis.
+/// 16-bit samples are scored on their low byte; packed 1-, 2-, and 4-bit
+/// images are not analyzed and are never flagged.
+pub fn detect_lsb_anomaly(image: &DecodedImage) -> StegoScore {
+    let channels = image.channels.max(1) as usize;
+    let color_channels = match channels {
+        2 | 4 => channels - 1,
+        n => n,
+    };
+    // 16-bit samples are big-endian, so the LSB is in the second byte
+    let sample_bytes = match image.bit_depth {
+        8 => 1,
+        16 => 2,
+        _ => 0,
+    };
+
+    let mut histogram = [0u64; 256];
+    let mut ones = 0u64;
+    let mut samples_analyzed = 0usize;
+
+    if sample_bytes > 0 {
+        for pixel in image.data.chunks_exact(channels * sample_bytes) {
+            for sample in pixel[..color_channels * sample_bytes].chunks_exact(sample_bytes) {
+                let low = sample[sample_bytes - 1];
+                histogram[low as usize] += 1;
+                ones += (low & 1) as u64;
+                samples_analyzed += 1;
+            }
+        }
+    }
+
+    // Pairs of values (2k, 2k+1) equalize under LSB replacement
+    let mut chi_square = 0.0;
+    let mut categories = 0u32;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            let diff = pair[0] as f64 - expected;
+            chi_square += diff * diff / expected;
+            categories += 1;
+        }
+    }
+
+    let embedding_probability = if categories > 1 {
+        regularized_gamma_q((categories - 1) as f64 / 2.0, chi_square / 2.0)
+    } else {
+        0.0
+    };
+
+    let lsb_entropy = if samples_analyzed == 0 {
+        0.0
+    } else {
+        let p1 = ones as f64 / samples_analyzed as f64;
+        let h = |p: f64| if p > 0.0 { -p * p.log2() } else { 0.0 };
+        h(p1) + h(1.0 - p1)
+    };
+
+    StegoScore {
+        chi_square,
+        embedding_probability,
+        lsb_entropy,
+        samples_analyzed,
+        suspicious: samples_analyzed >= MIN_SAMPLES && embedding_probability > SUSPICIOUS_THRESHOLD,
+    }
+}
+
+/// Natural log of the gamma function (Lanczos approximation, g=7)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let mut sum = COEFFS[0];
+    for (i, &c) in COEFFS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Upper regularized incomplete gamma Q(a, x) = 1 - P(a, x)
+///
+/// Equals the chi-square survival function for `a = df / 2`, `x = chi / 2`.
+fn regularized_gamma_q(a: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 500;
+    const EPSILON: f64 = 1e-12;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+
+    let log_prefix = -x + a * x.ln() - ln_gamma(a);
+
+    if x < a + 1.0 {
+        // Series expansion for P(a, x)
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..MAX_ITERATIONS {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (1.0 - sum * log_prefix.exp()).clamp(0.0, 1.0)
+    } else {
+        // Continued fraction for Q(a, x) (modified Lentz)
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..=MAX_ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (log_prefix.exp() * h).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Quantized gradient: only multiples of 4 occur, as in a posterized image
+    fn clean_image() -> DecodedImage {
+        let (width, height) = (64u32, 64u32);
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let v = (((x + y) * 4) % 256) as u8;
+                data.extend_from_slice(&[v, v.wrapping_add(64), v.wrapping_add(128), 255]);
+            }
+        }
+        DecodedImage {
+            data,
+            width,
+            height,
+            channels: 4,
+            bit_depth: 8,
+            had_alpha: true,
+            icc_profile: None,
+        }
+    }
+
+    #[test]
+    fn test_clean_image_not_suspicious() {
+        let score = detect_lsb_anomaly(&clean_image());
+        assert_eq!(score.samples_analyzed, 64 * 64 * 3);
+        assert!(score.embedding_probability < 0.05);
+        assert!(!score.suspicious);
+    }
+
+    #[test]
+    fn test_lsb_embedded_image_suspicious() {
+        let mut image = clean_image();
+        // Replace every color LSB with pseudo-random payload bits
+        let mut state = 0x2545_F491u32;
+        for (i, sample) in image.data.iter_mut().enumerate() {
+            if i % 4 == 3 {
+                continue;
+            }
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *sample = (*sample & !1) | (state & 1) as u8;
+        }
+
+        let score = detect_lsb_anomaly(&image);
+        assert!(score.embedding_probability > SUSPICIOUS_THRESHOLD);
+        assert!(score.lsb_entropy > 0.99);
+        assert!(score.suspicious);
+    }
+
+    #[test]
+    fn test_tiny_image_never_flagged() {
+        let image = DecodedImage {
+            data: vec![0, 1, 2, 3],
+            width: 1,
+            height: 1,
+            channels: 4,
+            bit_depth: 8,
+            had_alpha: true,
+            icc_profile: None,
+        };
+        assert!(!detect_lsb_anomaly(&image).suspicious);
+    }
+
+    #[test]
+    fn test_bit_depth_respected() {
+        // Widening each sample to 16 bits keeps its LSB in the low byte
+        let clean = clean_image();
+        let wide = DecodedImage {
+            data: clean.data.iter().flat_map(|&v| [v, v]).collect(),
+            bit_depth: 16,
+            ..clean.clone()
+        };
+        let (narrow, wide) = (detect_lsb_anomaly(&clean), detect_lsb_anomaly(&wide));
+        assert_eq!(wide.samples_analyzed, narrow.samples_analyzed);
+        assert_eq!(wide.chi_square, narrow.chi_square);
+
+        // Packed gray bytes are not samples
+        let packed = DecodedImage {
+            data: vec![0x55; 64 * 64 / 8],
+            channels: 1,
+            bit_depth: 1,
+            had_alpha: false,
+            ..clean
+        };
+        let score = detect_lsb_anomaly(&packed);
+        assert_eq!(score.samples_analyzed, 0);
+        assert!(!score.suspicious);
+    }
+
+    #[test]
+    fn test_gamma_q_matches_known_values() {
+        // Chi-square survival: df=2, x=2 -> exp(-1)
+        assert!((regularized_gamma_q(1.0, 1.0) - (-1.0f64).exp()).abs() < 1e-9);
+        assert!((regularized_gamma_q(5.0, 0.0) - 1.0).abs() < 1e-12);
+        assert!(regularized_gamma_q(10.0, 100.0) < 1e-20);
+    }
+}
@@ -0,0 +1,252 @@
+///! Structural element events (PNG chunks, JPEG segments, ISOBMFF boxes)
+///!
+///! Lets callers log or flag anomalies in container layout without writing
+///! their own parsers. The walk runs on the raw bytes ahead of the native
+///! decoder, so events are reported even for files the decoder then rejects.
+///!
+///! Security measures:
+///! - Bounds-checked, overflow-safe walks that stop at the first malformed
+///!   element instead of guessing
+///! - ISOBMFF descent is capped at MAX_BOX_NESTING_DEPTH
+///! - Nothing is decoded; events only carry offsets into the input
+use crate::api::MediaFormat;
+use crate::{
+    mp4_box_at, ISOBMFF_CONTAINER_BOXES, ISOBMFF_FULL_CONTAINER_BOXES, MAX_BOX_NESTING_DEPTH,
+};
+
+/// One chunk, segment, or box as found in the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructureEvent<'a> {
+    /// PNG chunk type or box type (as in the file), or JPEG marker name
+    pub kind: &'a str,
+    /// Offset of the element's first byte (length field or marker)
+    pub offset: usize,
+    /// Whole element length, headers and CRC included
+    pub len: usize,
+}
+
+/// Report each structural element of `data` to `on_structure`, in file order
+///
+/// PNG reports chunks, JPEG reports marker segments (not entropy-coded
+/// data), and HEIF/video containers report ISOBMFF boxes depth-first.
+/// Other formats report nothing.
+pub fn walk_structure<'a>(
+    format: MediaFormat,
+    data: &'a [u8],
+    mut on_structure: impl FnMut(StructureEvent<'a>),
+) {
+    match format {
+        MediaFormat::Png => walk_png(data, &mut on_structure),
+        MediaFormat::Jpeg => walk_jpeg(data, &mut on_structure),
+        MediaFormat::Heif | MediaFormat::VideoContainer => {
+            walk_boxes(data, 0, data.len(), 0, &mut on_structure)
+        }
+        #[cfg(feature = "avif")]
+        MediaFormat::Avif => walk_boxes(data, 0, data.len(), 0, &mut on_structure),
+        _ => {}
+    }
+}
+
+/// Four-character type as text; non-UTF-8 types are reported as "????"
+fn fourcc(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).unwrap_or("????")
+}
+
+fn walk_png<'a>(data: &'a [u8], on_structure: &mut impl FnMut(StructureEvent<'a>)) {
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return;
+    }
+    let mut pos = 8usize;
+    while let Some(header) = data.get(pos..pos.saturating_add(8)) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        // length + type + data + crc
+        let next = match pos.checked_add(12).and_then(|p| p.checked_add(length)) {
+            Some(next) if next <= data.len() => next,
+            _ => return,
+        };
+        let kind = fourcc(&data[pos + 4..pos + 8]);
+        on_structure(StructureEvent {
+            kind,
+            offset: pos,
+            len: next - pos,
+        });
+        if kind == "IEND" {
+            return;
+        }
+        pos = next;
+    }
+}
+
+fn jpeg_marker_name(marker: u8) -> &'static str {
+    const APP: [&str; 16] = [
+        "APP0", "APP1", "APP2", "APP3", "APP4", "APP5", "APP6", "APP7", "APP8", "APP9", "APP10",
+        "APP11", "APP12", "APP13", "APP14", "APP15",
+    ];
+    const RST: [&str; 8] = [
+        "RST0", "RST1", "RST2", "RST3", "RST4", "RST5", "RST6", "RST7",
+    ];
+    match marker {
+        0xC0 => "SOF0",
+        0xC1 => "SOF1",
+        0xC2 => "SOF2",
+        0xC3 => "SOF3",
+        0xC4 => "DHT",
+        0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => "SOF",
+        0xCC => "DAC",
+        0xD0..=0xD7 => RST[(marker - 0xD0) as usize],
+        0xD8 => "SOI",
+        0xD9 => "EOI",
+        0xDA => "SOS",
+        0xDB => "DQT",
+        0xDD => "DRI",
+        0xE0..=0xEF => APP[(marker - 0xE0) as usize],
+        0xFE => "COM",
+        _ => "marker",
+    }
+}
+
+fn walk_jpeg<'a>(data: &'a [u8], on_structure: &mut impl FnMut(StructureEvent<'a>)) {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return;
+    }
+    on_structure(StructureEvent {
+        kind: "SOI",
+        offset: 0,
+        len: 2,
+    });
+
+    let mut pos = 2usize;
+    loop {
+        match data.get(pos..pos.saturating_add(2)) {
+            Some(&[0xFF, 0xFF]) => {
+                // Fill byte before a marker
+                pos += 1;
+                continue;
+            }
+            Some(&[0xFF, _]) => {}
+            _ => return,
+        }
+        let marker = data[pos + 1];
+        let len = match marker {
+            // Standalone markers without a length field
+            0x01 | 0xD0..=0xD9 => 2,
+            _ => {
+                let length = match data.get(pos + 2..pos + 4) {
+                    Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]) as usize,
+                    None => return,
+                };
+                if length < 2 || pos + 2 + length > data.len() {
+                    return;
+                }
+                2 + length
+            }
+        };
+        on_structure(StructureEvent {
+            kind: jpeg_marker_name(marker),
+            offset: pos,
+            len,
+        });
+        if marker == 0xD9 {
+            return;
+        }
+        pos += len;
+
+        if marker == 0xDA {
+            // Skip entropy-coded data up to the next real marker
+            loop {
+                match data.get(pos..pos.saturating_add(2)) {
+                    Some(&[0xFF, next]) if next != 0x00 && !(0xD0..=0xD7).contains(&next) => break,
+                    Some(&[0xFF, _]) => pos += 2,
+                    Some(_) => pos += 1,
+                    None => return,
+                }
+            }
+        }
+    }
+}
+
+fn walk_boxes<'a>(
+    data: &'a [u8],
+    start: usize,
+    end: usize,
+    depth: usize,
+    on_structure: &mut impl FnMut(StructureEvent<'a>),
+) {
+    if depth > MAX_BOX_NESTING_DEPTH {
+        return;
+    }
+    let mut pos = start;
+    while pos < end {
+        let Ok((kind, payload, box_end)) = mp4_box_at(data, pos, end) else {
+            return;
+        };
+        on_structure(StructureEvent {
+            kind: fourcc(&data[pos + 4..pos + 8]),
+            offset: pos,
+            len: box_end - pos,
+        });
+        if ISOBMFF_CONTAINER_BOXES.contains(&&kind) {
+            walk_boxes(data, payload, box_end, depth + 1, on_structure);
+        } else if ISOBMFF_FULL_CONTAINER_BOXES.contains(&&kind) && box_end - payload >= 4 {
+            walk_boxes(data, payload + 4, box_end, depth + 1, on_structure);
+        }
+        pos = box_end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn events(format: MediaFormat, data: &[u8]) -> Vec<(String, usize, usize)> {
+        let mut out = Vec::new();
+        walk_structure(format, data, |event| {
+            out.push((event.kind.to_string(), event.offset, event.len))
+        });
+        out
+    }
+
+    #[test]
+    fn test_png_chunk_sequence() {
+        let png = test_support::png(
+            2,
+            1,
+            8,
+            0,
+            &[0, 0],
+            &[test_support::png_chunk(b"tEXt", b"k\0v")],
+        );
+        let chunks = events(MediaFormat::Png, &png);
+        let kinds: Vec<&str> = chunks.iter().map(|(kind, _, _)| kind.as_str()).collect();
+        assert_eq!(kinds, ["IHDR", "tEXt", "IDAT", "IEND"]);
+
+        // IHDR follows the signature; every chunk is length + 12 bytes
+        assert_eq!(chunks[0], ("IHDR".to_string(), 8, 25));
+        assert_eq!(chunks[1], ("tEXt".to_string(), 33, 15));
+        let (_, iend_offset, iend_len) = chunks[3];
+        assert_eq!(iend_offset + iend_len, png.len());
+
+        // Truncation stops the walk at the last complete chunk
+        assert_eq!(events(MediaFormat::Png, &png[..40]).len(), 1);
+    }
+
+    #[test]
+    fn test_jpeg_segment_sequence() {
+        let segments = events(MediaFormat::Jpeg, &test_support::JPEG_GRAY_8X8);
+        let kinds: Vec<&str> = segments.iter().map(|(kind, _, _)| kind.as_str()).collect();
+        assert_eq!(kinds.first(), Some(&"SOI"));
+        assert_eq!(kinds.last(), Some(&"EOI"));
+        assert!(kinds.contains(&"SOF0") && kinds.contains(&"SOS"));
+    }
+
+    #[test]
+    fn test_mp4_box_tree() {
+        let clip = include_bytes!("../../mov_bbb.mp4");
+        let boxes = events(MediaFormat::VideoContainer, clip);
+        assert_eq!(boxes[0], ("ftyp".to_string(), 0, 28));
+        assert!(boxes.contains(&("mdat".to_string(), 160, 778222)));
+        assert!(boxes.iter().any(|(kind, _, _)| kind == "trak"));
+        assert!(boxes.iter().any(|(kind, _, _)| kind == "stbl"));
+    }
+}
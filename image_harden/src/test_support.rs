@@ -0,0 +1,621 @@
+// Test-only builders for small, well-formed media files
+// Keeps unit tests self-contained without binary fixtures in the tree
+
+use std::sync::{Mutex, MutexGuard};
+
+// Serializes tests that assert on process-global metric values
+static METRICS_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn lock_metrics() -> MutexGuard<'static, ()> {
+    METRICS_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// MSB-first bit writer used by the FLAC builder
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    filled: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            current: 0,
+            filled: 0,
+        }
+    }
+
+    fn put(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> i) & 1) as u8;
+            self.filled += 1;
+            if self.filled == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn align(&mut self) {
+        while self.filled != 0 {
+            self.put(0, 1);
+        }
+    }
+}
+
+fn flac_crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn flac_crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// Build a mono FLAC stream with a single verbatim-coded frame
+// `samples.len()` must be between 16 and 256; `bits_per_sample` is 16 or 24.
+pub fn flac_mono(samples: &[i32], bits_per_sample: u32, sample_rate: u32) -> Vec<u8> {
+    assert!((16..=256).contains(&samples.len()));
+    let sample_size_code = match bits_per_sample {
+        16 => 0b100,
+        24 => 0b110,
+        _ => panic!("unsupported bit depth"),
+    };
+    let block_size = samples.len() as u64;
+
+    let mut out = b"fLaC".to_vec();
+
+    // STREAMINFO (last metadata block, 34 bytes)
+    let mut info = BitWriter::new();
+    info.put(1, 1);
+    info.put(0, 7);
+    info.put(34, 24);
+    info.put(block_size, 16); // min block size
+    info.put(block_size, 16); // max block size
+    info.put(0, 24); // min frame size (unknown)
+    info.put(0, 24); // max frame size (unknown)
+    info.put(sample_rate as u64, 20);
+    info.put(0, 3); // channels - 1
+    info.put(bits_per_sample as u64 - 1, 5);
+    info.put(block_size, 36); // total samples
+    for _ in 0..16 {
+        info.put(0, 8); // MD5 (unset)
+    }
+    out.extend_from_slice(&info.bytes);
+
+    // Frame header
+    let mut frame = BitWriter::new();
+    frame.put(0b11_1111_1111_1110, 14); // sync code
+    frame.put(0, 1); // reserved
+    frame.put(0, 1); // fixed blocksize
+    frame.put(0b0110, 4); // 8-bit (blocksize - 1) follows
+    frame.put(0b0000, 4); // sample rate from STREAMINFO
+    frame.put(0b0000, 4); // mono
+    frame.put(sample_size_code, 3);
+    frame.put(0, 1); // reserved
+    frame.put(0, 8); // frame number 0 (UTF-8 coded)
+    frame.put(block_size - 1, 8);
+    let crc8 = flac_crc8(&frame.bytes);
+    frame.put(crc8 as u64, 8);
+
+    // Verbatim subframe
+    frame.put(0, 1); // padding
+    frame.put(0b000001, 6); // VERBATIM
+    frame.put(0, 1); // no wasted bits
+    for &sample in samples {
+        let mask = (1u64 << bits_per_sample) - 1;
+        frame.put(sample as i64 as u64 & mask, bits_per_sample);
+    }
+    frame.align();
+    let crc16 = flac_crc16(&frame.bytes);
+    frame.put(crc16 as u64, 16);
+
+    out.extend_from_slice(&frame.bytes);
+    out
+}
+
+// MPEG-1 Layer III, 128 kbps, 44.1 kHz mono: silent frames with zeroed side info
+pub fn mp3_silence(frames: usize) -> Vec<u8> {
+    const FRAME_LEN: usize = 144 * 128_000 / 44_100;
+    let mut out = Vec::with_capacity(frames * FRAME_LEN);
+    for _ in 0..frames {
+        let start = out.len();
+        out.extend_from_slice(&[0xFF, 0xFB, 0x90, 0xC0]);
+        out.resize(start + FRAME_LEN, 0);
+    }
+    out
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// zlib stream using only stored (uncompressed) deflate blocks
+pub fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(65535).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 0x01 } else { 0x00 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    out
+}
+
+pub fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u32).to_be_bytes().to_vec();
+    let mut body = kind.to_vec();
+    body.extend_from_slice(data);
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&crc32(&body).to_be_bytes());
+    out
+}
+
+// Build a PNG from packed, unfiltered scanlines
+// `ancillary` chunks (already framed with `png_chunk`) go between IHDR and IDAT.
+pub fn png(
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    scanlines: &[u8],
+    ancillary: &[Vec<u8>],
+) -> Vec<u8> {
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => panic!("invalid color type"),
+    };
+    let row_bytes = (width as usize * channels * bit_depth as usize + 7) / 8;
+    assert_eq!(scanlines.len(), row_bytes * height as usize);
+
+    let mut raw = Vec::with_capacity((row_bytes + 1) * height as usize);
+    for row in scanlines.chunks(row_bytes.max(1)).take(height as usize) {
+        raw.push(0); // filter type: None
+        raw.extend_from_slice(row);
+    }
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    out.extend_from_slice(&png_chunk(b"IHDR", &ihdr));
+    for chunk in ancillary {
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&png_chunk(b"IDAT", &zlib_stored(&raw)));
+    out.extend_from_slice(&png_chunk(b"IEND", &[]));
+    out
+}
+
+// Solid-color 8-bit RGBA PNG
+pub fn png_rgba(width: u32, height: u32, rgba: [u8; 4]) -> Vec<u8> {
+    let pixels: Vec<u8> = rgba
+        .iter()
+        .copied()
+        .cycle()
+        .take(width as usize * height as usize * 4)
+        .collect();
+    png(width, height, 8, 6, &pixels, &[])
+}
+
+// Minimal v2 RGB display profile with an empty tag table
+pub fn icc_profile_rgb() -> Vec<u8> {
+    let mut profile = vec![0u8; 132];
+    profile[0..4].copy_from_slice(&132u32.to_be_bytes());
+    profile[8] = 2;
+    profile[12..16].copy_from_slice(b"mntr");
+    profile[16..20].copy_from_slice(b"RGB ");
+    profile[20..24].copy_from_slice(b"XYZ ");
+    profile[36..40].copy_from_slice(b"acsp");
+    // D50 PCS illuminant
+    profile[68..72].copy_from_slice(&0xF6D6u32.to_be_bytes());
+    profile[72..76].copy_from_slice(&0x1_0000u32.to_be_bytes());
+    profile[76..80].copy_from_slice(&0xD32Du32.to_be_bytes());
+    profile
+}
+
+// iCCP chunk payload carrying `profile`, ready for `png_chunk`
+pub fn iccp(profile: &[u8]) -> Vec<u8> {
+    let mut data = b"test\0\0".to_vec();
+    data.extend_from_slice(&zlib_stored(profile));
+    data
+}
+
+// One solid-color APNG frame: fcTL placement plus dispose_op and blend_op
+pub struct TestApngFrame {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: [u8; 4],
+    pub dispose_op: u8, // 0 none, 1 background, 2 previous
+    pub blend_op: u8,   // 0 source, 1 over
+}
+
+// Build an 8-bit RGBA APNG: a transparent default image outside the
+// animation, then one fcTL/fdAT pair per frame
+pub fn apng(width: u32, height: u32, frames: &[TestApngFrame]) -> Vec<u8> {
+    let still = png_rgba(width, height, [0, 0, 0, 0]);
+    let ihdr_end = 8 + 25;
+    let iend_start = still.len() - 12;
+
+    let mut actl = (frames.len() as u32).to_be_bytes().to_vec();
+    actl.extend_from_slice(&0u32.to_be_bytes()); // num_plays: loop forever
+    let mut out = still[..ihdr_end].to_vec();
+    out.extend_from_slice(&png_chunk(b"acTL", &actl));
+    out.extend_from_slice(&still[ihdr_end..iend_start]);
+
+    let mut sequence = 0u32;
+    for frame in frames {
+        let mut fctl = sequence.to_be_bytes().to_vec();
+        for value in [frame.width, frame.height, frame.left, frame.top] {
+            fctl.extend_from_slice(&value.to_be_bytes());
+        }
+        fctl.extend_from_slice(&[0, 1, 0, 10, frame.dispose_op, frame.blend_op]); // 1/10 s
+        out.extend_from_slice(&png_chunk(b"fcTL", &fctl));
+
+        let mut raw = Vec::new();
+        for _ in 0..frame.height {
+            raw.push(0); // filter type: None
+            for _ in 0..frame.width {
+                raw.extend_from_slice(&frame.rgba);
+            }
+        }
+        let mut fdat = (sequence + 1).to_be_bytes().to_vec();
+        fdat.extend_from_slice(&zlib_stored(&raw));
+        out.extend_from_slice(&png_chunk(b"fdAT", &fdat));
+        sequence += 2;
+    }
+    out.extend_from_slice(&png_chunk(b"IEND", &[]));
+    out
+}
+
+// 8x8 baseline JPEG (libjpeg q90, 4:2:0), solid RGB (200, 100, 50)
+pub const JPEG_RGB_8X8: [u8; 288] = [
+    0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0x4A, 0x46, 0x49, 0x46, 0x00, 0x01, 0x01, 0x00, 0x00, 0x01,
+    0x00, 0x01, 0x00, 0x00, 0xFF, 0xDB, 0x00, 0x43, 0x00, 0x03, 0x02, 0x02, 0x03, 0x02, 0x02, 0x03,
+    0x03, 0x03, 0x03, 0x04, 0x03, 0x03, 0x04, 0x05, 0x08, 0x05, 0x05, 0x04, 0x04, 0x05, 0x0A, 0x07,
+    0x07, 0x06, 0x08, 0x0C, 0x0A, 0x0C, 0x0C, 0x0B, 0x0A, 0x0B, 0x0B, 0x0D, 0x0E, 0x12, 0x10, 0x0D,
+    0x0E, 0x11, 0x0E, 0x0B, 0x0B, 0x10, 0x16, 0x10, 0x11, 0x13, 0x14, 0x15, 0x15, 0x15, 0x0C, 0x0F,
+    0x17, 0x18, 0x16, 0x14, 0x18, 0x12, 0x14, 0x15, 0x14, 0xFF, 0xDB, 0x00, 0x43, 0x01, 0x03, 0x04,
+    0x04, 0x05, 0x04, 0x05, 0x09, 0x05, 0x05, 0x09, 0x14, 0x0D, 0x0B, 0x0D, 0x14, 0x14, 0x14, 0x14,
+    0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14,
+    0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14,
+    0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0x14, 0xFF, 0xC0,
+    0x00, 0x11, 0x08, 0x00, 0x08, 0x00, 0x08, 0x03, 0x01, 0x22, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11,
+    0x01, 0xFF, 0xC4, 0x00, 0x15, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xFF, 0xC4, 0x00, 0x14, 0x10, 0x01, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xC4,
+    0x00, 0x15, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x07, 0x08, 0xFF, 0xC4, 0x00, 0x14, 0x11, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xDA, 0x00, 0x0C, 0x03,
+    0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x00, 0x3F, 0x00, 0x90, 0x00, 0x7A, 0x90, 0x7F, 0xFF, 0xD9,
+];
+
+// 8x8 single-component baseline JPEG (libjpeg q90), solid gray 128
+pub use crate::self_test::SAMPLE_JPEG as JPEG_GRAY_8X8;
+
+// 8x8 Adobe CMYK JPEG (libjpeg q100, no subsampling, APP14 transform 0)
+// Solid stored (inverted) CMYK (255, 127, 0, 255), i.e. RGB (255, 127, 0)
+pub const JPEG_ADOBE_CMYK_8X8: [u8; 180] = [
+    0xFF, 0xD8, 0xFF, 0xEE, 0x00, 0x0E, 0x41, 0x64, 0x6F, 0x62, 0x65, 0x00, 0x64, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0xFF, 0xDB, 0x00, 0x43, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0xFF, 0xC0, 0x00, 0x14, 0x08, 0x00, 0x08, 0x00, 0x08,
+    0x04, 0x43, 0x11, 0x00, 0x4D, 0x11, 0x00, 0x59, 0x11, 0x00, 0x4B, 0x11, 0x00, 0xFF, 0xC4, 0x00,
+    0x16, 0x00, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x0A, 0x04, 0x0B, 0xFF, 0xC4, 0x00, 0x14, 0x10, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xDA, 0x00, 0x0E, 0x04,
+    0x43, 0x00, 0x4D, 0x00, 0x59, 0x00, 0x4B, 0x00, 0x00, 0x3F, 0x00, 0x7F, 0x09, 0xD9, 0xFF, 0x00,
+    0x9F, 0xC3, 0xFF, 0xD9,
+];
+
+// JPEG_ADOBE_CMYK_8X8's pixels encoded as YCCK (APP14 transform 2)
+pub const JPEG_ADOBE_YCCK_8X8: [u8; 291] = [
+    0xFF, 0xD8, 0xFF, 0xEE, 0x00, 0x0E, 0x41, 0x64, 0x6F, 0x62, 0x65, 0x00, 0x64, 0x00, 0x00, 0x00,
+    0x00, 0x02, 0xFF, 0xDB, 0x00, 0x43, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0xFF, 0xDB, 0x00, 0x43, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0xFF, 0xC0, 0x00, 0x14,
+    0x08, 0x00, 0x08, 0x00, 0x08, 0x04, 0x01, 0x11, 0x00, 0x02, 0x11, 0x01, 0x03, 0x11, 0x01, 0x04,
+    0x11, 0x00, 0xFF, 0xC4, 0x00, 0x15, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x0A, 0xFF, 0xC4, 0x00, 0x14, 0x10, 0x01, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF,
+    0xC4, 0x00, 0x14, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x0A, 0xFF, 0xC4, 0x00, 0x14, 0x11, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xDA, 0x00, 0x0E, 0x04,
+    0x01, 0x00, 0x02, 0x11, 0x03, 0x11, 0x04, 0x00, 0x00, 0x3F, 0x00, 0x1F, 0x95, 0x40, 0xD7, 0xAF,
+    0xE1, 0xFF, 0xD9,
+];
+
+// 8x8 lossless WebP (libwebp), RGB (200, 100, 50), top half opaque, bottom half alpha 0
+pub const WEBP_LOSSLESS_ALPHA_8X8: [u8; 40] = [
+    0x52, 0x49, 0x46, 0x46, 0x20, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38, 0x4C,
+    0x14, 0x00, 0x00, 0x00, 0x2F, 0x07, 0xC0, 0x01, 0x10, 0x0F, 0x30, 0x64, 0x83, 0x3C, 0x32, 0xF3,
+    0x1F, 0xF0, 0x98, 0xFF, 0x88, 0xFE, 0xC7, 0x03,
+];
+
+// 8x8 lossy WebP with ALPH chunk (libwebp q90), RGB (200, 100, 50) at alpha 128
+pub const WEBP_LOSSY_ALPHA_8X8: [u8; 106] = [
+    0x52, 0x49, 0x46, 0x46, 0x62, 0x00, 0x00, 0x00, 0x57, 0x45, 0x42, 0x50, 0x56, 0x50, 0x38, 0x58,
+    0x0A, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x07, 0x00, 0x00, 0x41, 0x4C,
+    0x50, 0x48, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x07, 0x50, 0xC0, 0x88, 0x08, 0x44, 0x44, 0xFF, 0x03,
+    0x56, 0x50, 0x38, 0x20, 0x32, 0x00, 0x00, 0x00, 0xD0, 0x01, 0x00, 0x9D, 0x01, 0x2A, 0x08, 0x00,
+    0x08, 0x00, 0x00, 0xC0, 0x12, 0x25, 0xA0, 0x02, 0x74, 0xBA, 0x01, 0xF8, 0x00, 0x03, 0xB0, 0x00,
+    0xFE, 0xDA, 0x26, 0xFF, 0xEE, 0xF3, 0x7E, 0xD3, 0xD7, 0xB4, 0xF5, 0xFD, 0x4C, 0xFF, 0xF8, 0xCA,
+    0x9F, 0x20, 0x3F, 0xE3, 0x2A, 0x7F, 0xC5, 0xCC, 0x00, 0x00,
+];
+
+// 8x8 lossy WebP without alpha (libwebp q90), RGB (200, 100, 50)
+pub use crate::self_test::SAMPLE_WEBP as WEBP_LOSSY_8X8;
+
+// Build an animated WebP (VP8X + ANIM) whose frames each place
+// WEBP_LOSSLESS_ALPHA_8X8's VP8L bitstream at an even (x, y) offset
+pub fn webp_animated(width: u32, height: u32, offsets: &[(u32, u32)]) -> Vec<u8> {
+    let le24 = |value: u32| value.to_le_bytes()[..3].to_vec();
+    let chunk = |fourcc: &[u8; 4], payload: &[u8]| {
+        let mut out = fourcc.to_vec();
+        out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(payload);
+        if payload.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    };
+
+    let mut vp8x = vec![0x12, 0, 0, 0]; // alpha and animation flags
+    vp8x.extend(le24(width - 1));
+    vp8x.extend(le24(height - 1));
+    let mut body = b"WEBP".to_vec();
+    body.extend(chunk(b"VP8X", &vp8x));
+    body.extend(chunk(b"ANIM", &[0, 0, 0, 0, 0, 0])); // transparent, loop forever
+    for &(x, y) in offsets {
+        let mut anmf = le24(x / 2);
+        anmf.extend(le24(y / 2));
+        anmf.extend(le24(8 - 1));
+        anmf.extend(le24(8 - 1));
+        anmf.extend(le24(100)); // duration in ms
+        anmf.push(0); // alpha-blend, no disposal
+        anmf.extend_from_slice(&WEBP_LOSSLESS_ALPHA_8X8[12..]);
+        body.extend(chunk(b"ANMF", &anmf));
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend(body);
+    out
+}
+
+// One image block for the GIF builder
+pub struct TestGifFrame {
+    pub left: u16,
+    pub top: u16,
+    pub width: u16,
+    pub height: u16,
+    pub indices: Vec<u8>,        // Row-major palette indices, width * height
+    pub transparent: Option<u8>, // GCE transparent color index
+    pub delay_cs: u16,           // GCE delay in 1/100 s
+    pub disposal: u8,            // GCE disposal method (0-3)
+}
+
+impl TestGifFrame {
+    // Opaque frame at (left, top) without a graphic control extension
+    pub fn new(left: u16, top: u16, width: u16, height: u16, indices: Vec<u8>) -> Self {
+        assert_eq!(indices.len(), width as usize * height as usize);
+        Self {
+            left,
+            top,
+            width,
+            height,
+            indices,
+            transparent: None,
+            delay_cs: 0,
+            disposal: 0,
+        }
+    }
+}
+
+// LZW stream that never grows the code table: a clear code is emitted
+// before the table would need wider codes, so every code is min_code_size + 1 bits.
+fn gif_lzw(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u32 << min_code_size;
+    let width = min_code_size as u32 + 1;
+    let per_clear = (1usize << min_code_size) - 2;
+
+    let mut out = Vec::new();
+    let (mut acc, mut bits) = (0u32, 0u32);
+    let mut emit = |code: u32, out: &mut Vec<u8>| {
+        acc |= code << bits;
+        bits += width;
+        while bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    };
+
+    for chunk in indices.chunks(per_clear) {
+        emit(clear, &mut out);
+        for &index in chunk {
+            emit(index as u32, &mut out);
+        }
+    }
+    emit(clear + 1, &mut out);
+    if bits > 0 {
+        out.push(acc as u8);
+    }
+    out
+}
+
+fn gif_sub_blocks(data: &[u8], out: &mut Vec<u8>) {
+    for block in data.chunks(255) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+}
+
+// Build a GIF89a with a global palette (padded to a power of two) and the given frames
+pub fn gif(width: u16, height: u16, palette: &[[u8; 3]], frames: &[TestGifFrame]) -> Vec<u8> {
+    assert!(!palette.is_empty() && palette.len() <= 256);
+    let table_bits = (1..=8).find(|b| (1usize << b) >= palette.len()).unwrap();
+    let min_code_size = table_bits.max(2);
+
+    let mut out = b"GIF89a".to_vec();
+    out.extend_from_slice(&width.to_le_bytes());
+    out.extend_from_slice(&height.to_le_bytes());
+    out.push(0x80 | 0x70 | (table_bits - 1)); // global table, 8-bit color resolution
+    out.push(0); // background color index
+    out.push(0); // pixel aspect ratio
+    for i in 0..(1usize << table_bits) {
+        out.extend_from_slice(&palette.get(i).copied().unwrap_or([0, 0, 0]));
+    }
+
+    for frame in frames {
+        if frame.transparent.is_some() || frame.delay_cs != 0 || frame.disposal != 0 {
+            out.extend_from_slice(&[0x21, 0xF9, 0x04]);
+            out.push((frame.disposal & 0x07) << 2 | frame.transparent.is_some() as u8);
+            out.extend_from_slice(&frame.delay_cs.to_le_bytes());
+            out.push(frame.transparent.unwrap_or(0));
+            out.push(0);
+        }
+
+        out.push(0x2C);
+        for value in [frame.left, frame.top, frame.width, frame.height] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.push(0); // no local color table, not interlaced
+        out.push(min_code_size);
+        gif_sub_blocks(&gif_lzw(&frame.indices, min_code_size), &mut out);
+    }
+
+    out.push(0x3B);
+    out
+}
+
+// ISOBMFF box: 32-bit size, four-character type, payload
+pub fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(payload);
+    out
+}
+
+// EBML element: ID bytes as written, 8-byte size, payload
+pub fn ebml(id: u32, payload: &[u8]) -> Vec<u8> {
+    let id_bytes = id.to_be_bytes();
+    let skip = id_bytes.iter().position(|&b| b != 0).unwrap_or(3);
+    let mut out = id_bytes[skip..].to_vec();
+    out.push(0x01);
+    out.extend_from_slice(&(payload.len() as u64).to_be_bytes()[1..]);
+    out.extend_from_slice(payload);
+    out
+}
+
+fn ebml_uint(id: u32, value: u64) -> Vec<u8> {
+    ebml(id, &value.to_be_bytes())
+}
+
+// Matroska Tracks entry (1 = video, 2 = audio, 0x11 = subtitle)
+pub fn mkv_track(number: u64, track_type: u64, codec: &str) -> Vec<u8> {
+    let mut entry = ebml_uint(0xD7, number);
+    entry.extend(ebml_uint(0x73C5, number));
+    entry.extend(ebml_uint(0x83, track_type));
+    entry.extend(ebml(0x86, codec.as_bytes()));
+    if track_type == 1 {
+        let mut video = ebml_uint(0xB0, 16);
+        video.extend(ebml_uint(0xBA, 16));
+        entry.extend(ebml(0xE0, &video));
+    }
+    ebml(0xAE, &entry)
+}
+
+// Build a Matroska file: EBML header, then a Segment holding Info (plus
+// `info_extra`), Tracks with `tracks`, and `segment_extra`
+pub fn mkv(info_extra: &[Vec<u8>], tracks: &[Vec<u8>], segment_extra: &[Vec<u8>]) -> Vec<u8> {
+    let mut header = ebml_uint(0x4286, 1);
+    header.extend(ebml_uint(0x42F7, 1));
+    header.extend(ebml_uint(0x42F2, 4));
+    header.extend(ebml_uint(0x42F3, 8));
+    header.extend(ebml(0x4282, b"matroska"));
+    header.extend(ebml_uint(0x4287, 4));
+    header.extend(ebml_uint(0x4285, 2));
+
+    let mut info = ebml_uint(0x2AD7B1, 1_000_000);
+    info.extend(ebml(0x4D80, b"test_support"));
+    info.extend(ebml(0x5741, b"test_support"));
+    info.extend(ebml(0x4489, &1000.0f64.to_be_bytes()));
+    for element in info_extra {
+        info.extend_from_slice(element);
+    }
+
+    let mut segment = ebml(0x1549A966, &info);
+    segment.extend(ebml(0x1654AE6B, &tracks.concat()));
+    for element in segment_extra {
+        segment.extend_from_slice(element);
+    }
+
+    let mut out = ebml(0x1A45DFA3, &header);
+    out.extend(ebml(0x18538067, &segment));
+    out
+}